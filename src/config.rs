@@ -1,9 +1,16 @@
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, fmt};
+
 use crate::{
-    parse::{parse_command, Command, Operation},
-    tile::TileType,
+    parse::{
+        has_flag, parse_command, parse_cycle, parse_priority, parse_rule, scope_from_str, Command,
+        Cycle, Operation, ParseError, Scope, SCOPES,
+    },
+    rule::Condition,
+    suggest::closest,
+    tile::{Params, TileType},
 };
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone)]
 enum ConfigValue {
     Inner(u32),
     Outer(u32),
@@ -15,188 +22,1354 @@ enum ConfigValue {
     SmartH(Option<u32>),
     SmartV(Option<u32>),
     Dim(i32),
+    ZoomRatio(u32),
+}
+
+impl ConfigValue {
+    fn field(&self) -> Field {
+        match self {
+            ConfigValue::Inner(_) => Field::Inner,
+            ConfigValue::Outer(_) => Field::Outer,
+            ConfigValue::Ratio(_) => Field::Ratio,
+            ConfigValue::Main(_) => Field::Main,
+            ConfigValue::Tile(_) => Field::Tile,
+            ConfigValue::Pad(_) => Field::Pad,
+            ConfigValue::Monocle(_) => Field::Monocle,
+            ConfigValue::SmartH(_) => Field::SmartH,
+            ConfigValue::SmartV(_) => Field::SmartV,
+            ConfigValue::Dim(_) => Field::Dim,
+            ConfigValue::ZoomRatio(_) => Field::ZoomRatio,
+        }
+    }
+
+    fn apply_to(&self, config: &mut Config) {
+        match *self {
+            ConfigValue::Inner(v) => config.inner = v,
+            ConfigValue::Outer(v) => config.outer = v,
+            ConfigValue::Ratio(v) => config.ratio = v,
+            ConfigValue::Main(v) => config.main = v,
+            ConfigValue::Tile(v) => config.tile = v,
+            ConfigValue::Pad(v) => config.pad = v,
+            ConfigValue::Monocle(v) => config.monocle = v,
+            ConfigValue::SmartH(v) => config.smart_h = v,
+            ConfigValue::SmartV(v) => config.smart_v = v,
+            ConfigValue::Dim(v) => config.dim = v,
+            ConfigValue::ZoomRatio(v) => config.zoom_ratio = v,
+        }
+    }
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+enum Field {
+    Inner,
+    Outer,
+    Ratio,
+    Main,
+    Tile,
+    Pad,
+    Monocle,
+    SmartH,
+    SmartV,
+    Dim,
+    ZoomRatio,
+}
+
+const FIELDS: [Field; 11] = [
+    Field::Inner,
+    Field::Outer,
+    Field::Ratio,
+    Field::Main,
+    Field::Tile,
+    Field::Pad,
+    Field::Monocle,
+    Field::SmartH,
+    Field::SmartV,
+    Field::Dim,
+    Field::ZoomRatio,
+];
+
+// The smallest and largest value of each numeric setting. Adjusting or setting
+// past either end stops there.
+fn limits(field: Field) -> (i32, i32) {
+    match field {
+        Field::Inner | Field::Outer | Field::SmartH | Field::SmartV => (0, 1024),
+        Field::Ratio | Field::ZoomRatio => (10, 90),
+        Field::Main => (1, 16),
+        Field::Dim => (-100, 100),
+        Field::Tile | Field::Pad | Field::Monocle => unreachable!("{:?} isn't a number", field),
+    }
+}
+
+#[derive(Clone)]
 struct ConfigEntry {
-    output: Option<String>,
-    tags: Option<u32>,
+    priority: i32,
+    order: u64,
+    ephemeral: bool,
     value: ConfigValue,
 }
 
+// Every command, and the words it takes other than numbers. This is only used
+// for suggestions, so it doesn't need to know which combinations make sense.
+const COMMANDS: [(&str, &[&str]); 35] = [
+    ("view-padding", &[]),
+    ("outer-padding", &[]),
+    ("main-location", &["left", "top", "right", "bottom"]),
+    ("main-count", &[]),
+    ("main-ratio", &[]),
+    ("flip", &[]),
+    ("rotate", &["cw", "ccw"]),
+    ("pad", &["on", "off"]),
+    ("monocle", &["on", "off"]),
+    ("smart-padding", &["off"]),
+    ("smart-padding-h", &[]),
+    ("smart-padding-v", &[]),
+    ("move-split-up", &[]),
+    ("move-split-down", &[]),
+    ("move-split-left", &[]),
+    ("move-split-right", &[]),
+    ("diminish", &[]),
+    ("zoom", &[]),
+    ("last-layout", &[]),
+    ("zoom-ratio", &[]),
+    (
+        "multi-tag-policy",
+        &["last", "lowest", "highest", "default"],
+    ),
+    ("default-scope", &SCOPES),
+    ("precedence", &["specificity", "order"]),
+    ("when", &[]),
+    ("clear-rules", &[]),
+    ("lock", &[]),
+    ("unlock", &[]),
+    ("tag-name", &[]),
+    ("output-alias", &[]),
+    ("preset-save", &[]),
+    ("preset-load", &[]),
+    ("preset-list", &[]),
+    ("alias", &[]),
+    ("toggle", &[]),
+    ("cycle", &[]),
+];
+
+// the fields a command can change
+fn command_fields(command: &Command) -> &'static [Field] {
+    match command.namespace() {
+        "view-padding" => &[Field::Inner],
+        "outer-padding" => &[Field::Outer],
+        "main-ratio" | "move-split-up" | "move-split-down" | "move-split-left"
+        | "move-split-right" => &[Field::Ratio],
+        "main-count" => &[Field::Main],
+        "main-location" | "flip" | "rotate" => &[Field::Tile],
+        "pad" => &[Field::Pad],
+        "monocle" => &[Field::Monocle],
+        "smart-padding" => &[Field::SmartH, Field::SmartV],
+        "smart-padding-h" => &[Field::SmartH],
+        "smart-padding-v" => &[Field::SmartV],
+        "diminish" => &[Field::Dim],
+        "zoom" => &[Field::Main, Field::Ratio, Field::Tile],
+        "last-layout" => &[Field::Tile, Field::Monocle, Field::Main],
+        "zoom-ratio" => &[Field::ZoomRatio],
+        "preset-load" => &FIELDS,
+        _ => &[],
+    }
+}
+
+// A command that's only applied to layouts matching the condition.
+#[derive(Clone)]
+struct Rule {
+    tags: Option<u32>,
+    output: Option<String>,
+    condition: Condition,
+    cmd: String,
+    order: u64,
+}
+
+// default < output < tag < output and tag
+fn rank(tags: Option<u32>, output: Option<&String>) -> u32 {
+    match (tags, output) {
+        (None, None) => 0,
+        (None, Some(_)) => 1,
+        (Some(_), None) => 2,
+        (Some(_), Some(_)) => 3,
+    }
+}
+
+// Entries keyed by output, then by tags and field. There's only ever one
+// entry per key, so a newer write replaces an older one.
+type Entries = HashMap<Option<String>, HashMap<(Option<u32>, Field), ConfigEntry>>;
+
+// Built configs, by tags and output, until anything they're built from changes.
+type Cache = RefCell<HashMap<(Option<u32>, Option<String>), Config>>;
+
+// How to pick a config when more than one tag is visible.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum MultiTagPolicy {
+    Last,
+    Lowest,
+    Highest,
+    Default,
+}
+
+// Which entry wins when more than one applies. Specificity orders them by
+// scope, while Insertion is the old behavior: the last one set wins.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Precedence {
+    Specificity,
+    Insertion,
+}
+
+// Why a command wasn't applied.
+#[derive(PartialEq, Clone, Debug)]
+pub enum ApplyError {
+    Parse(ParseError),
+    UnknownCommand {
+        name: String,
+        suggestion: Option<String>,
+    },
+    InvalidValue {
+        value: String,
+        suggestion: Option<String>,
+    },
+    UnknownPreset {
+        name: String,
+        suggestion: Option<String>,
+    },
+    ReservedName(String),
+    RecursiveAlias(String),
+    Alias {
+        name: String,
+        cmd: String,
+        error: Box<ApplyError>,
+    },
+    OutOfRange {
+        value: i32,
+        min: i32,
+        max: i32,
+    },
+    Locked {
+        tags: Option<u32>,
+        output: Option<String>,
+    },
+}
+
+impl From<ParseError> for ApplyError {
+    fn from(e: ParseError) -> Self {
+        ApplyError::Parse(e)
+    }
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyError::Parse(e) => write!(f, "{}", e),
+            ApplyError::UnknownCommand { name, suggestion } => {
+                write!(f, "unknown command {}", name)?;
+                write_suggestion(f, suggestion)
+            }
+            ApplyError::InvalidValue { value, suggestion } => {
+                write!(f, "invalid value {}", value)?;
+                write_suggestion(f, suggestion)
+            }
+            ApplyError::UnknownPreset { name, suggestion } => {
+                write!(f, "unknown preset {}", name)?;
+                write_suggestion(f, suggestion)
+            }
+            ApplyError::ReservedName(name) => write!(f, "{} is already a command", name),
+            ApplyError::RecursiveAlias(name) => write!(f, "alias {} uses itself", name),
+            ApplyError::Alias { name, cmd, error } => {
+                write!(f, "in alias {}, \"{}\": {}", name, cmd, error)
+            }
+            ApplyError::OutOfRange { value, min, max } => {
+                write!(f, "{} is out of range ({} to {})", value, min, max)
+            }
+            ApplyError::Locked { tags, output } => {
+                match tags {
+                    Some(t) => write!(f, "tagmask {}", t)?,
+                    None => write!(f, "all tags")?,
+                }
+
+                match output {
+                    Some(o) => write!(f, " on {} is locked", o),
+                    None => write!(f, " on all outputs is locked"),
+                }
+            }
+        }
+    }
+}
+
+fn write_suggestion(f: &mut fmt::Formatter<'_>, suggestion: &Option<String>) -> fmt::Result {
+    match suggestion {
+        Some(s) => write!(f, ", did you mean {}?", s),
+        None => Ok(()),
+    }
+}
+
+// What's wrong with a command that wasn't understood, along with a guess at
+// what was meant.
+fn diagnose(command: &Command) -> ApplyError {
+    let namespace = command.namespace();
+    let names = COMMANDS.iter().map(|(name, _)| *name);
+
+    let values = match COMMANDS.iter().find(|(name, _)| *name == namespace) {
+        Some((_, values)) => values,
+        None => {
+            return ApplyError::UnknownCommand {
+                name: namespace.to_string(),
+                suggestion: closest(namespace, names).map(|s| s.to_string()),
+            }
+        }
+    };
+
+    let value = match *command {
+        Command::Single(_) => String::new(),
+        Command::Numeric { value, .. } => value.to_string(),
+        Command::Textual { value, .. } => value.to_string(),
+        Command::Keyed { key, value, .. } => format!("{} {}", key, value),
+    };
+
+    ApplyError::InvalidValue {
+        suggestion: closest(&value, values.iter().copied()).map(|s| s.to_string()),
+        value,
+    }
+}
+
+// What zooming in replaced, to put back when zooming out, and the ratio it set.
+#[derive(Clone, Copy, Debug)]
+struct Zoom {
+    main: u32,
+    ratio: u32,
+    tile: TileType,
+    zoomed: u32,
+}
+
+// The parts of a config that make up its arrangement, for last-layout.
+#[derive(PartialEq, Clone, Copy, Debug)]
+struct Layout {
+    tile: TileType,
+    monocle: bool,
+    main: u32,
+}
+
+impl Layout {
+    fn of(config: &Config) -> Layout {
+        Layout {
+            tile: config.tile,
+            monocle: config.monocle,
+            main: config.main,
+        }
+    }
+
+    fn apply_to(self, config: &mut Config) {
+        config.tile = self.tile;
+        config.monocle = self.monocle;
+        config.main = self.main;
+    }
+}
+
+#[derive(Clone)]
 pub struct ConfigStorage {
-    entries: Vec<ConfigEntry>,
+    entries: Entries,
+    cache: Cache,
+    order: u64,
+    presets: HashMap<String, Config>,
+    policies: HashMap<Option<String>, MultiTagPolicy>,
+    tag_names: HashMap<String, u32>,
+    output_aliases: HashMap<String, String>,
+    aliases: HashMap<String, String>,
+    cycles: HashMap<(Option<u32>, Option<String>, String), usize>,
+    zooms: HashMap<(Option<u32>, Option<String>), Zoom>,
+    layouts: HashMap<(Option<u32>, Option<String>), Layout>,
+    precedence: Precedence,
+    default_scope: Scope,
+    locks: Vec<(Option<u32>, Option<String>)>,
+    rules: Vec<Rule>,
+    warnings: Vec<ApplyError>,
 }
 
 impl ConfigStorage {
     pub fn new() -> ConfigStorage {
         ConfigStorage {
-            entries: Vec::new(),
+            entries: HashMap::new(),
+            cache: RefCell::new(HashMap::new()),
+            order: 0,
+            presets: HashMap::new(),
+            policies: HashMap::new(),
+            tag_names: HashMap::new(),
+            output_aliases: HashMap::new(),
+            aliases: HashMap::new(),
+            cycles: HashMap::new(),
+            zooms: HashMap::new(),
+            layouts: HashMap::new(),
+            precedence: Precedence::Specificity,
+            default_scope: Scope::TagOutput,
+            locks: Vec::new(),
+            rules: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn tag_names(&self) -> &HashMap<String, u32> {
+        &self.tag_names
+    }
+
+    // The names of the tags in a mask, with any unnamed ones as numbers, or
+    // None if none of them have a name.
+    pub fn tag_label(&self, tags: u32) -> Option<String> {
+        let indexes: Vec<u32> = (1..=32).filter(|i| tags & (1 << (i - 1)) != 0).collect();
+
+        let names: Vec<Option<&String>> = indexes
+            .iter()
+            .map(|i| {
+                self.tag_names
+                    .iter()
+                    .find(|(_, index)| *index == i)
+                    .map(|(name, _)| name)
+            })
+            .collect();
+
+        if names.iter().all(|n| n.is_none()) {
+            return None;
+        }
+
+        let parts: Vec<String> = indexes
+            .iter()
+            .zip(names)
+            .map(|(i, name)| match name {
+                Some(name) => name.clone(),
+                None => i.to_string(),
+            })
+            .collect();
+
+        Some(parts.join(","))
+    }
+
+    // Problems with commands that were still applied, like values that had to
+    // be clamped, since the last time they were taken.
+    pub fn take_warnings(&mut self) -> Vec<ApplyError> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub fn alias(&self, name: &str) -> Option<&String> {
+        self.aliases.get(name)
+    }
+
+    // an alias without any commands is removed
+    pub fn add_alias(&mut self, name: &str, cmds: &str) -> Result<(), ApplyError> {
+        if COMMANDS.iter().any(|(n, _)| *n == name) {
+            return Err(ApplyError::ReservedName(name.to_string()));
+        }
+
+        if cmds.trim().is_empty() {
+            self.aliases.remove(name);
+        } else {
+            self.aliases.insert(name.to_string(), cmds.to_string());
         }
+
+        Ok(())
+    }
+
+    pub fn default_scope(&self) -> Scope {
+        self.default_scope
+    }
+
+    pub fn policy(&self, output: &str) -> MultiTagPolicy {
+        self.policies
+            .iter()
+            .filter_map(|(pattern, policy)| match pattern {
+                Some(pattern) => self.match_output(pattern, output).map(|s| (s, policy)),
+                None => Some((0, policy)),
+            })
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, policy)| *policy)
+            .unwrap_or(MultiTagPolicy::Last)
     }
 
     pub fn build(&self, tags: Option<u32>, output: Option<&str>) -> Config {
+        let key = (tags, output.map(|o| o.to_string()));
+
+        if let Some(config) = self.cache.borrow().get(&key) {
+            return config.clone();
+        }
+
+        let config = self.resolve(tags, output);
+        self.cache.borrow_mut().insert(key, config.clone());
+
+        config
+    }
+
+    fn resolve(&self, tags: Option<u32>, output: Option<&str>) -> Config {
         let mut config = Config::new();
 
-        for e in &self.entries {
-            if (e.output.as_deref() == output || e.output.is_none())
-                && (e.tags == tags || e.tags.is_none())
-            {
-                match e.value {
-                    ConfigValue::Inner(v) => config.inner = v,
-                    ConfigValue::Outer(v) => config.outer = v,
-                    ConfigValue::Ratio(v) => config.ratio = v,
-                    ConfigValue::Main(v) => config.main = v,
-                    ConfigValue::Tile(v) => config.tile = v,
-                    ConfigValue::Pad(v) => config.pad = v,
-                    ConfigValue::Monocle(v) => config.monocle = v,
-                    ConfigValue::SmartH(v) => config.smart_h = v,
-                    ConfigValue::SmartV(v) => config.smart_v = v,
-                    ConfigValue::Dim(v) => config.dim = v,
+        let scopes = match tags {
+            Some(_) => vec![None, tags],
+            None => vec![None],
+        };
+
+        // the more specific the match, the later it's applied
+        let mut matches = Vec::new();
+
+        for (pattern, entries) in &self.entries {
+            let specificity = match (pattern, output) {
+                (None, _) => 0,
+                (Some(pattern), Some(output)) => match self.match_output(pattern, output) {
+                    Some(s) => s,
+                    None => continue,
+                },
+                _ => continue,
+            };
+
+            for &t in &scopes {
+                for field in FIELDS {
+                    if let Some(e) = entries.get(&(t, field)) {
+                        let key = match self.precedence {
+                            Precedence::Specificity => {
+                                (e.priority, rank(t, pattern.as_ref()), specificity, e.order)
+                            }
+                            Precedence::Insertion => (e.priority, 0, 0, e.order),
+                        };
+
+                        matches.push((key, &e.value));
+                    }
                 }
             }
         }
 
+        matches.sort_by_key(|(key, _)| *key);
+
+        for (_, value) in matches {
+            value.apply_to(&mut config);
+        }
+
         config
     }
 
-    fn add(&mut self, entry: ConfigEntry) {
-        // get rid of any dupes
-        self.entries.retain(|e| e != &entry);
+    // Build the config for an actual layout, with the rules that match it applied
+    // on top. Rules go in the same order as entries, from least to most
+    // specific scope, and otherwise in the order they were added.
+    pub fn build_with(&self, tags: Option<u32>, output: Option<&str>, params: &Params) -> Config {
+        let mut config = self.build(tags, output);
+
+        let mut rules: Vec<((u32, u32, u64), &Rule)> = self
+            .rules
+            .iter()
+            .filter(|r| r.tags.is_none() || r.tags == tags)
+            .filter_map(|r| {
+                let specificity = match (r.output.as_deref(), output) {
+                    (None, _) => 0,
+                    (Some(pattern), Some(output)) => self.match_output(pattern, output)?,
+                    _ => return None,
+                };
+
+                let key = match self.precedence {
+                    Precedence::Specificity => {
+                        (rank(r.tags, r.output.as_ref()), specificity, r.order)
+                    }
+                    Precedence::Insertion => (0, 0, r.order),
+                };
+
+                Some((key, r))
+            })
+            .collect();
+
+        rules.sort_by_key(|(key, _)| *key);
+
+        for (_, rule) in rules {
+            if rule.condition.matches(params) {
+                if let Ok(command) = parse_command(&rule.cmd) {
+                    config.update(command);
+                }
+            }
+        }
 
-        // then add our new value
-        self.entries.push(entry);
+        config
     }
 
-    fn apply(&mut self, tags: Option<u32>, output: Option<&str>, config: &Config) {
-        let existing = self.build(tags, output);
+    // a newer rule replaces one for the same scope, condition and setting
+    fn add_rule(
+        &mut self,
+        tags: Option<u32>,
+        output: Option<&str>,
+        condition: Condition,
+        cmd: String,
+    ) {
+        let namespace = parse_command(&cmd).ok().map(|c| c.namespace());
+
+        self.rules.retain(|r| {
+            !(r.tags == tags
+                && r.output.as_deref() == output
+                && r.condition == condition
+                && parse_command(&r.cmd).ok().map(|c| c.namespace()) == namespace)
+        });
 
-        let make_entry = |value: ConfigValue| ConfigEntry {
+        self.order += 1;
+
+        self.rules.push(Rule {
             tags,
             output: output.map(|o| o.to_string()),
+            condition,
+            cmd,
+            order: self.order,
+        });
+    }
+
+    // aliases are resolved at match time, so changing one re-targets every
+    // entry that uses it
+    fn match_output(&self, pattern: &str, output: &str) -> Option<u32> {
+        if pattern == output {
+            return Some(3);
+        }
+
+        output_specificity(&self.expand_aliases(pattern), output)
+    }
+
+    // could two output patterns both match the same output?
+    fn outputs_overlap(&self, a: &str, b: &str) -> bool {
+        let a = self.expand_aliases(a);
+        let b = self.expand_aliases(b);
+
+        let negations = |p: &str| p.split(',').all(|part| part.starts_with('!'));
+
+        // names and globs from one that the other matches
+        let matches = |pattern: &str, other: &str| {
+            other
+                .split(',')
+                .filter(|part| !part.starts_with('!'))
+                .any(|part| output_specificity(pattern, part).is_some())
+        };
+
+        a == b || (negations(&a) && negations(&b)) || matches(&a, &b) || matches(&b, &a)
+    }
+
+    fn expand_aliases(&self, pattern: &str) -> String {
+        let parts: Vec<String> = pattern
+            .split(',')
+            .flat_map(|part| {
+                let (negation, name) = match part.strip_prefix('!') {
+                    Some(name) => ("!", name),
+                    None => ("", part),
+                };
+
+                match self.output_aliases.get(name) {
+                    Some(target) => target
+                        .split(',')
+                        .map(|t| format!("{}{}", negation, t))
+                        .collect(),
+                    None => vec![part.to_string()],
+                }
+            })
+            .collect();
+
+        parts.join(",")
+    }
+
+    fn add(
+        &mut self,
+        tags: Option<u32>,
+        output: Option<&str>,
+        priority: i32,
+        ephemeral: bool,
+        value: ConfigValue,
+    ) {
+        self.order += 1;
+
+        let entry = ConfigEntry {
+            priority,
+            order: self.order,
+            ephemeral,
             value,
         };
 
-        if existing.inner != config.inner {
-            self.add(make_entry(ConfigValue::Inner(config.inner)));
+        self.entries
+            .entry(output.map(|o| o.to_string()))
+            .or_default()
+            .insert((tags, entry.value.field()), entry);
+
+        self.invalidate();
+    }
+
+    fn invalidate(&mut self) {
+        self.cache.get_mut().clear();
+    }
+
+    // only write what's different from what's already in effect
+    fn apply(
+        &mut self,
+        tags: Option<u32>,
+        output: Option<&str>,
+        priority: i32,
+        ephemeral: bool,
+        config: &Config,
+    ) {
+        let existing = self.build(tags, output).values();
+
+        for value in config.values() {
+            if !existing.contains(&value) {
+                self.add(tags, output, priority, ephemeral, value);
+            }
+        }
+    }
+
+    // write these fields even if they're already in effect, so they don't
+    // depend on what they'd otherwise be inherited from
+    fn apply_fields(
+        &mut self,
+        tags: Option<u32>,
+        output: Option<&str>,
+        priority: i32,
+        ephemeral: bool,
+        config: &Config,
+        fields: &[Field],
+    ) {
+        for value in config.values() {
+            if fields.contains(&value.field()) {
+                self.add(tags, output, priority, ephemeral, value);
+            }
+        }
+    }
+
+    // is any of these fields set for exactly this scope, but ephemeral when
+    // this isn't, or the other way around?
+    fn ephemeral_differs(
+        &self,
+        tags: Option<u32>,
+        output: Option<&str>,
+        fields: &[Field],
+        ephemeral: bool,
+    ) -> bool {
+        self.entries
+            .get(&output.map(|o| o.to_string()))
+            .is_some_and(|entries| {
+                fields.iter().any(|&field| {
+                    entries
+                        .get(&(tags, field))
+                        .is_some_and(|e| e.ephemeral != ephemeral)
+                })
+            })
+    }
+
+    // Remove every entry for these fields that would win over the value just
+    // set for the scope, anywhere they both apply.
+    fn cascade(
+        &mut self,
+        tags: Option<u32>,
+        output: Option<&str>,
+        priority: i32,
+        fields: &[Field],
+        force: bool,
+    ) {
+        let scope_rank = rank(tags, output.map(|o| o.to_string()).as_ref());
+
+        // only a single output can't lose to another pattern of the same rank
+        let exact = output.is_some_and(|o| !self.expand_aliases(o).contains(['*', '?', ',', '!']));
+
+        let mut doomed = Vec::new();
+
+        for (pattern, entries) in &self.entries {
+            let overlaps = match (output, pattern) {
+                (Some(output), Some(pattern)) => self.outputs_overlap(output, pattern),
+                _ => true,
+            };
+
+            if !overlaps {
+                continue;
+            }
+
+            for (&(t, field), e) in entries {
+                if !fields.contains(&field)
+                    || (t == tags && pattern.as_deref() == output)
+                    || !(tags.is_none() || t.is_none() || t == tags)
+                {
+                    continue;
+                }
+
+                let wins = match e.priority.cmp(&priority) {
+                    Ordering::Greater => true,
+                    Ordering::Less => false,
+                    Ordering::Equal => match self.precedence {
+                        Precedence::Insertion => false,
+                        Precedence::Specificity => {
+                            match rank(t, pattern.as_ref()).cmp(&scope_rank) {
+                                Ordering::Greater => true,
+                                Ordering::Less => false,
+                                Ordering::Equal => !exact,
+                            }
+                        }
+                    },
+                };
+
+                if wins && (force || !self.is_locked(t, pattern.as_deref())) {
+                    doomed.push((pattern.clone(), (t, field)));
+                }
+            }
+        }
+
+        for (pattern, key) in doomed {
+            if let Some(entries) = self.entries.get_mut(&pattern) {
+                entries.remove(&key);
+            }
+        }
+
+        self.invalidate();
+    }
+
+    // Forget the ephemeral settings of a tag, on this output. Each output has
+    // its own tags, so settings for all outputs, or a pattern of them, aren't
+    // for any one tag emptying and are left alone.
+    pub fn clear_ephemeral(&mut self, tags: u32, output: &str) {
+        if let Some(entries) = self.entries.get_mut(&Some(output.to_string())) {
+            entries.retain(|(t, _), e| !(e.ephemeral && *t == Some(tags)));
+        }
+
+        self.invalidate();
+    }
+
+    // a lock covers its own scope, and everything narrower
+    fn is_locked(&self, tags: Option<u32>, output: Option<&str>) -> bool {
+        self.locks.iter().any(|(t, o)| {
+            (t.is_none() || *t == tags)
+                && match (o, output) {
+                    (None, _) => true,
+                    (Some(o), Some(output)) => self.match_output(o, output).is_some(),
+                    (Some(_), None) => false,
+                }
+        })
+    }
+
+    #[cfg(test)]
+    fn apply_with(&mut self, tags: Option<u32>, output: Option<&str>, f: impl Fn(&mut Config)) {
+        let mut config = self.build(tags, output);
+        f(&mut config);
+        self.apply(tags, output, 0, false, &config);
+    }
+
+    pub fn apply_cmd(
+        &mut self,
+        tags: Option<u32>,
+        output: Option<&str>,
+        cmd: &str,
+    ) -> Result<(), ApplyError> {
+        let force = has_flag("--force", cmd);
+        let explicit_priority = parse_priority(cmd)?;
+        let priority = explicit_priority.unwrap_or(0);
+
+        if let Some((condition, rule)) = parse_rule(cmd) {
+            let command = parse_command(&rule)?;
+            self.warnings.extend(check_range(&command));
+
+            if !Config::new().update(command) {
+                return Err(diagnose(&command));
+            }
+
+            if !force && self.is_locked(tags, output) {
+                return Err(self.locked(tags, output));
+            }
+
+            self.add_rule(tags, output, condition, rule);
+            return Ok(());
+        }
+
+        if let Some(cycle) = parse_cycle(cmd) {
+            return self.apply_cycle(tags, output, cycle?);
         }
 
-        if existing.outer != config.outer {
-            self.add(make_entry(ConfigValue::Outer(config.outer)));
+        let existing = self.build(tags, output);
+        let mut config = existing.clone();
+        let command = parse_command(cmd)?;
+        self.warnings.extend(check_range(&command));
+
+        // only kept once the change has gone through
+        let mut zoom = None;
+
+        let fields = command_fields(&command);
+
+        // these change the scope without going through its config, so they're
+        // checked before anything happens
+        let unconfigured = matches!(
+            command,
+            Command::Single("clear-rules")
+                | Command::Textual {
+                    namespace: "preset-save",
+                    ..
+                }
+        );
+
+        if unconfigured && !force && self.is_locked(tags, output) {
+            return Err(self.locked(tags, output));
         }
 
-        if existing.ratio != config.ratio {
-            self.add(make_entry(ConfigValue::Ratio(config.ratio)));
+        match command {
+            Command::Single("lock") => {
+                let lock = (tags, output.map(|o| o.to_string()));
+
+                if !self.locks.contains(&lock) {
+                    self.locks.push(lock);
+                }
+            }
+            Command::Single("unlock") => {
+                let lock = (tags, output.map(|o| o.to_string()));
+                self.locks.retain(|l| l != &lock);
+            }
+            Command::Single("clear-rules") => {
+                self.rules
+                    .retain(|r| !(r.tags == tags && r.output.as_deref() == output));
+            }
+            Command::Single("preset-list") => {
+                let mut names: Vec<&String> = self.presets.keys().collect();
+                names.sort();
+
+                for name in names {
+                    println!("{}", name);
+                }
+            }
+            Command::Single("zoom") => {
+                // once the zoomed values are gone (changed, or cleared with the
+                // ephemeral settings) there's nothing to zoom out of
+                let zoomed = self
+                    .zooms
+                    .get(&(tags, output.map(|o| o.to_string())))
+                    .filter(|z| config.main == 1 && config.ratio == z.zoomed);
+
+                match zoomed {
+                    Some(z) => {
+                        config.main = z.main;
+                        config.ratio = z.ratio;
+                        config.tile = z.tile;
+                        zoom = Some(None);
+                    }
+                    None => {
+                        zoom = Some(Some(Zoom {
+                            main: config.main,
+                            ratio: config.ratio,
+                            tile: config.tile,
+                            zoomed: config.zoom_ratio,
+                        }));
+
+                        config.main = 1;
+                        config.ratio = config.zoom_ratio;
+                    }
+                }
+            }
+            Command::Single("last-layout") => {
+                if let Some(layout) = self.layouts.get(&(tags, output.map(|o| o.to_string()))) {
+                    layout.apply_to(&mut config);
+                }
+            }
+            Command::Textual {
+                namespace: "preset-save",
+                value,
+            } => {
+                self.presets.insert(value.to_string(), config.clone());
+            }
+            Command::Textual {
+                namespace: "preset-load",
+                value,
+            } => match self.presets.get(value) {
+                Some(preset) => config = preset.clone(),
+                None => {
+                    return Err(ApplyError::UnknownPreset {
+                        name: value.to_string(),
+                        suggestion: closest(value, self.presets.keys().map(|k| k.as_str()))
+                            .map(|s| s.to_string()),
+                    })
+                }
+            },
+            Command::Textual {
+                namespace: "multi-tag-policy",
+                value,
+            } => {
+                let policy = match value {
+                    "last" => Some(MultiTagPolicy::Last),
+                    "lowest" => Some(MultiTagPolicy::Lowest),
+                    "highest" => Some(MultiTagPolicy::Highest),
+                    "default" => Some(MultiTagPolicy::Default),
+                    _ => None,
+                };
+
+                match policy {
+                    Some(p) => {
+                        self.policies.insert(output.map(|o| o.to_string()), p);
+                    }
+                    None => return Err(diagnose(&command)),
+                }
+            }
+            Command::Keyed {
+                namespace: "tag-name",
+                key,
+                value,
+            } => match key.parse::<u32>() {
+                Ok(index)
+                    if (1..=32).contains(&index)
+                        && value.parse::<u32>().is_err()
+                        && value != "all"
+                        && !value.contains(|c: char| c == ',' || c.is_whitespace()) =>
+                {
+                    self.tag_names.retain(|_, i| *i != index);
+                    self.tag_names.insert(value.to_string(), index);
+                }
+                _ => return Err(diagnose(&command)),
+            },
+            Command::Keyed {
+                namespace: "output-alias",
+                key,
+                value,
+            } => {
+                if key == "all" {
+                    return Err(diagnose(&command));
+                } else {
+                    self.output_aliases
+                        .insert(key.to_string(), value.to_string());
+                    self.invalidate();
+                }
+            }
+            Command::Textual {
+                namespace: "default-scope",
+                value,
+            } => match scope_from_str(value) {
+                Some(scope) => self.default_scope = scope,
+                None => return Err(diagnose(&command)),
+            },
+            Command::Textual {
+                namespace: "precedence",
+                value: "specificity",
+            } => {
+                self.precedence = Precedence::Specificity;
+                self.invalidate();
+            }
+            Command::Textual {
+                namespace: "precedence",
+                value: "order",
+            } => {
+                self.precedence = Precedence::Insertion;
+                self.invalidate();
+            }
+            command => {
+                if !config.update(command) {
+                    return Err(diagnose(&command));
+                }
+            }
+        };
+
+        if !force && self.is_locked(tags, output) {
+            // only complain if it would have made a difference
+            if config != existing {
+                return Err(self.locked(tags, output));
+            }
+
+            return Ok(());
         }
 
-        if existing.main != config.main {
-            self.add(make_entry(ConfigValue::Main(config.main)));
+        // broader changes still go through, but not for the locked scopes
+        let pinned: Vec<(Option<u32>, Option<String>, Config)> = if force {
+            Vec::new()
+        } else {
+            self.locks
+                .iter()
+                .map(|(t, o)| (*t, o.clone(), self.build(*t, o.as_deref())))
+                .collect()
+        };
+
+        let ephemeral = has_flag("--ephemeral", cmd);
+
+        // A preset brings every one of its settings along, and a priority or
+        // whether it's ephemeral only counts if the value is written along
+        // with it. A cascade removes what the value might be inherited from.
+        if command.namespace() == "preset-load" {
+            self.apply_fields(tags, output, priority, ephemeral, &config, &FIELDS);
+        } else if explicit_priority.is_some()
+            || has_flag("--cascade", cmd)
+            || self.ephemeral_differs(tags, output, fields, ephemeral)
+        {
+            self.apply_fields(tags, output, priority, ephemeral, &config, fields);
+        } else {
+            self.apply(tags, output, priority, ephemeral, &config);
         }
 
-        if existing.tile != config.tile {
-            self.add(make_entry(ConfigValue::Tile(config.tile)));
+        match zoom {
+            Some(Some(z)) => {
+                self.zooms.insert((tags, output.map(|o| o.to_string())), z);
+            }
+            Some(None) => {
+                self.zooms.remove(&(tags, output.map(|o| o.to_string())));
+            }
+            None => {}
         }
 
-        if existing.pad != config.pad {
-            self.add(make_entry(ConfigValue::Pad(config.pad)));
+        // remember what we're leaving, so last-layout can come back to it
+        if Layout::of(&existing) != Layout::of(&config) {
+            self.layouts
+                .insert((tags, output.map(|o| o.to_string())), Layout::of(&existing));
         }
 
-        if existing.monocle != config.monocle {
-            self.add(make_entry(ConfigValue::Monocle(config.monocle)));
+        if has_flag("--cascade", cmd) {
+            self.cascade(tags, output, priority, fields, force);
         }
 
-        if existing.smart_h != config.smart_h {
-            self.add(make_entry(ConfigValue::SmartH(config.smart_h)));
+        for (t, o, before) in pinned {
+            self.pin(t, o.as_deref(), priority, &before);
         }
 
-        if existing.smart_v != config.smart_v {
-            self.add(make_entry(ConfigValue::SmartV(config.smart_v)));
+        Ok(())
+    }
+
+    // Apply the value after the last one applied for the same setting, values
+    // and scope, going back to the first after the end.
+    fn apply_cycle(
+        &mut self,
+        tags: Option<u32>,
+        output: Option<&str>,
+        cycle: Cycle,
+    ) -> Result<(), ApplyError> {
+        let key = (
+            tags,
+            output.map(|o| o.to_string()),
+            format!("{} {}", cycle.setting, cycle.values.join(" ")),
+        );
+
+        let next = match self.cycles.get(&key) {
+            Some(i) => (i + 1) % cycle.values.len(),
+            None => 0,
+        };
+
+        let cmd = format!("{} {} {}", cycle.setting, cycle.values[next], cycle.options);
+        self.apply_cmd(tags, output, cmd.trim())?;
+        self.cycles.insert(key, next);
+
+        Ok(())
+    }
+
+    // Put back whatever a broader change moved in a locked scope. Written with
+    // the same priority and after it, so it wins either way.
+    fn pin(&mut self, tags: Option<u32>, output: Option<&str>, priority: i32, before: &Config) {
+        let after = self.build(tags, output).values();
+
+        for value in before.values() {
+            if !after.contains(&value) {
+                self.add(tags, output, priority, false, value);
+            }
         }
+    }
 
-        if existing.dim != config.dim {
-            self.add(make_entry(ConfigValue::Dim(config.dim)));
+    fn locked(&self, tags: Option<u32>, output: Option<&str>) -> ApplyError {
+        ApplyError::Locked {
+            tags,
+            output: output.map(|o| o.to_string()),
         }
     }
+}
 
-    #[cfg(test)]
-    fn apply_with(&mut self, tags: Option<u32>, output: Option<&str>, f: impl Fn(&mut Config)) {
-        let mut config = self.build(tags, output);
-        f(&mut config);
-        self.apply(tags, output, &config);
+// Setting a value outside of what a setting allows clamps it, but it's still
+// probably a mistake.
+fn check_range(command: &Command) -> Option<ApplyError> {
+    let (namespace, value) = match *command {
+        Command::Numeric {
+            namespace,
+            operation: Operation::Set,
+            value,
+        } => (namespace, value),
+        _ => return None,
+    };
+
+    // these take how far to move, not a ratio
+    if namespace.starts_with("move-split-") {
+        return None;
     }
 
-    pub fn apply_cmd(&mut self, tags: Option<u32>, output: Option<&str>, cmd: &str) {
-        let mut config = self.build(tags, output);
+    command_fields(command)
+        .iter()
+        .map(|&field| limits(field))
+        .find(|(min, max)| !(min..=max).contains(&&value))
+        .map(|(min, max)| ApplyError::OutOfRange { value, min, max })
+}
+
+// How closely an output pattern matches an output name, or None if it doesn't
+// match at all. Patterns are comma separated lists of names or globs, any of
+// which can be negated with a leading "!". An exact name beats a glob, which
+// beats a pattern made up only of negations.
+fn output_specificity(pattern: &str, output: &str) -> Option<u32> {
+    if pattern == output {
+        return Some(3);
+    }
+
+    let mut best = None;
+    let mut positive = false;
+
+    for part in pattern.split(',') {
+        match part.strip_prefix('!') {
+            Some(negated) => {
+                if glob(negated.as_bytes(), output.as_bytes()) {
+                    return None;
+                }
+            }
+            None => {
+                positive = true;
 
-        match parse_command(cmd) {
-            Command::Single("flip") => match config.tile {
-                TileType::Left => config.tile = TileType::Right,
-                TileType::Top => config.tile = TileType::Bottom,
-                TileType::Right => config.tile = TileType::Left,
-                TileType::Bottom => config.tile = TileType::Top,
+                if part == output {
+                    best = best.max(Some(3));
+                } else if glob(part.as_bytes(), output.as_bytes()) {
+                    best = best.max(Some(2));
+                }
+            }
+        }
+    }
+
+    if positive {
+        best
+    } else {
+        Some(1)
+    }
+}
+
+// "*" matches anything, "?" matches any one character
+fn glob(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob(&pattern[1..], name) || (!name.is_empty() && glob(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Config {
+    pub inner: u32,
+    pub outer: u32,
+    pub ratio: u32,
+    pub main: u32,
+    pub tile: TileType,
+    pub pad: bool,
+    pub monocle: bool,
+    pub smart_h: Option<u32>,
+    pub smart_v: Option<u32>,
+    pub dim: i32,
+    pub zoom_ratio: u32,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            inner: 6,
+            outer: 6,
+            ratio: 55,
+            main: 1,
+            tile: TileType::Left,
+            pad: false,
+            monocle: false,
+            smart_h: None,
+            smart_v: None,
+            dim: 0,
+            zoom_ratio: 80,
+        }
+    }
+
+    fn values(&self) -> [ConfigValue; 11] {
+        [
+            ConfigValue::Inner(self.inner),
+            ConfigValue::Outer(self.outer),
+            ConfigValue::Ratio(self.ratio),
+            ConfigValue::Main(self.main),
+            ConfigValue::Tile(self.tile),
+            ConfigValue::Pad(self.pad),
+            ConfigValue::Monocle(self.monocle),
+            ConfigValue::SmartH(self.smart_h),
+            ConfigValue::SmartV(self.smart_v),
+            ConfigValue::Dim(self.dim),
+            ConfigValue::ZoomRatio(self.zoom_ratio),
+        ]
+    }
+
+    // apply a command that only changes the config itself, returning false if
+    // it isn't one
+    pub fn update(&mut self, command: Command) -> bool {
+        match command {
+            Command::Single("flip") => match self.tile {
+                TileType::Left => self.tile = TileType::Right,
+                TileType::Top => self.tile = TileType::Bottom,
+                TileType::Right => self.tile = TileType::Left,
+                TileType::Bottom => self.tile = TileType::Top,
+            },
+            Command::Textual {
+                namespace: "rotate",
+                value: "cw",
+            } => match self.tile {
+                TileType::Left => self.tile = TileType::Top,
+                TileType::Top => self.tile = TileType::Right,
+                TileType::Right => self.tile = TileType::Bottom,
+                TileType::Bottom => self.tile = TileType::Left,
+            },
+            Command::Textual {
+                namespace: "rotate",
+                value: "ccw",
+            } => match self.tile {
+                TileType::Left => self.tile = TileType::Bottom,
+                TileType::Top => self.tile = TileType::Left,
+                TileType::Right => self.tile = TileType::Top,
+                TileType::Bottom => self.tile = TileType::Right,
             },
             Command::Single("pad") => {
-                config.pad = !config.pad;
+                self.pad = !self.pad;
             }
             Command::Single("monocle") => {
-                config.monocle = !config.monocle;
+                self.monocle = !self.monocle;
             }
             Command::Textual {
                 namespace: "main-location",
                 value: "left",
-            } => config.tile = TileType::Left,
+            } => self.tile = TileType::Left,
             Command::Textual {
                 namespace: "main-location",
                 value: "top",
-            } => config.tile = TileType::Top,
+            } => self.tile = TileType::Top,
             Command::Textual {
                 namespace: "main-location",
                 value: "right",
-            } => config.tile = TileType::Right,
+            } => self.tile = TileType::Right,
             Command::Textual {
                 namespace: "main-location",
                 value: "bottom",
-            } => config.tile = TileType::Bottom,
+            } => self.tile = TileType::Bottom,
             Command::Textual {
                 namespace: "pad",
                 value: "on",
-            } => config.pad = true,
+            } => self.pad = true,
             Command::Textual {
                 namespace: "pad",
                 value: "off",
-            } => config.pad = false,
+            } => self.pad = false,
             Command::Textual {
                 namespace: "monocle",
                 value: "on",
-            } => config.monocle = true,
+            } => self.monocle = true,
             Command::Textual {
                 namespace: "monocle",
                 value: "off",
-            } => config.monocle = false,
+            } => self.monocle = false,
             Command::Textual {
                 namespace: "smart-padding",
                 value: "off",
             } => {
-                config.smart_h = None;
-                config.smart_v = None
+                self.smart_h = None;
+                self.smart_v = None
             }
             Command::Numeric {
                 namespace: "view-padding",
                 operation,
                 value,
             } => match operation {
-                Operation::Add => config.inc_inner(value),
-                Operation::Subtract => config.dec_inner(value),
-                Operation::Set => config.set_inner(value),
+                Operation::Add => self.inc_inner(value.unsigned_abs()),
+                Operation::Subtract => self.dec_inner(value.unsigned_abs()),
+                Operation::Set => self.set_inner(value),
             },
             Command::Numeric {
                 namespace: "outer-padding",
                 operation,
                 value,
             } => match operation {
-                Operation::Add => config.inc_outer(value),
-                Operation::Subtract => config.dec_outer(value),
-                Operation::Set => config.set_outer(value),
+                Operation::Add => self.inc_outer(value.unsigned_abs()),
+                Operation::Subtract => self.dec_outer(value.unsigned_abs()),
+                Operation::Set => self.set_outer(value),
             },
             Command::Numeric {
                 namespace: "smart-padding",
@@ -204,16 +1377,16 @@ impl ConfigStorage {
                 value,
             } => match operation {
                 Operation::Add => {
-                    config.inc_smart_h(value);
-                    config.inc_smart_v(value)
+                    self.inc_smart_h(value.unsigned_abs());
+                    self.inc_smart_v(value.unsigned_abs())
                 }
                 Operation::Subtract => {
-                    config.dec_smart_h(value);
-                    config.dec_smart_v(value)
+                    self.dec_smart_h(value.unsigned_abs());
+                    self.dec_smart_v(value.unsigned_abs())
                 }
                 Operation::Set => {
-                    config.set_smart_h(value);
-                    config.set_smart_v(value)
+                    self.set_smart_h(value);
+                    self.set_smart_v(value)
                 }
             },
             Command::Numeric {
@@ -221,62 +1394,62 @@ impl ConfigStorage {
                 operation,
                 value,
             } => match operation {
-                Operation::Add => config.inc_smart_h(value),
-                Operation::Subtract => config.dec_smart_h(value),
-                Operation::Set => config.set_smart_h(value),
+                Operation::Add => self.inc_smart_h(value.unsigned_abs()),
+                Operation::Subtract => self.dec_smart_h(value.unsigned_abs()),
+                Operation::Set => self.set_smart_h(value),
             },
             Command::Numeric {
                 namespace: "smart-padding-v",
                 operation,
                 value,
             } => match operation {
-                Operation::Add => config.inc_smart_v(value),
-                Operation::Subtract => config.dec_smart_v(value),
-                Operation::Set => config.set_smart_v(value),
+                Operation::Add => self.inc_smart_v(value.unsigned_abs()),
+                Operation::Subtract => self.dec_smart_v(value.unsigned_abs()),
+                Operation::Set => self.set_smart_v(value),
             },
             Command::Numeric {
                 namespace: "main-ratio",
                 operation,
                 value,
             } => match operation {
-                Operation::Add => config.inc_ratio(value),
-                Operation::Subtract => config.dec_ratio(value),
-                Operation::Set => config.set_ratio(value),
+                Operation::Add => self.inc_ratio(value.unsigned_abs()),
+                Operation::Subtract => self.dec_ratio(value.unsigned_abs()),
+                Operation::Set => self.set_ratio(value),
             },
             Command::Numeric {
                 namespace: "move-split-up",
                 operation: Operation::Set,
                 value,
-            } => match config.tile {
-                TileType::Top => config.dec_ratio(value),
-                TileType::Bottom => config.inc_ratio(value),
+            } => match self.tile {
+                TileType::Top => self.dec_ratio(value.unsigned_abs()),
+                TileType::Bottom => self.inc_ratio(value.unsigned_abs()),
                 _ => {}
             },
             Command::Numeric {
                 namespace: "move-split-down",
                 operation: Operation::Set,
                 value,
-            } => match config.tile {
-                TileType::Top => config.inc_ratio(value),
-                TileType::Bottom => config.dec_ratio(value),
+            } => match self.tile {
+                TileType::Top => self.inc_ratio(value.unsigned_abs()),
+                TileType::Bottom => self.dec_ratio(value.unsigned_abs()),
                 _ => {}
             },
             Command::Numeric {
                 namespace: "move-split-left",
                 operation: Operation::Set,
                 value,
-            } => match config.tile {
-                TileType::Left => config.dec_ratio(value),
-                TileType::Right => config.inc_ratio(value),
+            } => match self.tile {
+                TileType::Left => self.dec_ratio(value.unsigned_abs()),
+                TileType::Right => self.inc_ratio(value.unsigned_abs()),
                 _ => {}
             },
             Command::Numeric {
                 namespace: "move-split-right",
                 operation: Operation::Set,
                 value,
-            } => match config.tile {
-                TileType::Left => config.inc_ratio(value),
-                TileType::Right => config.dec_ratio(value),
+            } => match self.tile {
+                TileType::Left => self.inc_ratio(value.unsigned_abs()),
+                TileType::Right => self.dec_ratio(value.unsigned_abs()),
                 _ => {}
             },
             Command::Numeric {
@@ -284,64 +1457,45 @@ impl ConfigStorage {
                 operation,
                 value,
             } => match operation {
-                Operation::Add => config.inc_main(value),
-                Operation::Subtract => config.dec_main(value),
-                Operation::Set => config.set_main(value),
+                Operation::Add => self.inc_main(value.unsigned_abs()),
+                Operation::Subtract => self.dec_main(value.unsigned_abs()),
+                Operation::Set => self.set_main(value),
             },
             Command::Numeric {
                 namespace: "diminish",
                 operation,
                 value,
             } => match operation {
-                Operation::Add => config.inc_dim(value),
-                Operation::Subtract => config.dec_dim(value),
-                Operation::Set => config.set_dim(value),
+                Operation::Add => self.inc_dim(value.unsigned_abs()),
+                Operation::Subtract => self.dec_dim(value.unsigned_abs()),
+                Operation::Set => self.set_dim(value),
+            },
+            Command::Numeric {
+                namespace: "zoom-ratio",
+                operation,
+                value,
+            } => match operation {
+                Operation::Add => self.inc_zoom_ratio(value.unsigned_abs()),
+                Operation::Subtract => self.dec_zoom_ratio(value.unsigned_abs()),
+                Operation::Set => self.set_zoom_ratio(value),
             },
-            _ => println!("invalid command {}", cmd),
+            _ => return false,
         };
 
-        self.apply(tags, output, &config)
+        true
     }
-}
-
-#[derive(Clone, Debug)]
-pub struct Config {
-    pub inner: u32,
-    pub outer: u32,
-    pub ratio: u32,
-    pub main: u32,
-    pub tile: TileType,
-    pub pad: bool,
-    pub monocle: bool,
-    pub smart_h: Option<u32>,
-    pub smart_v: Option<u32>,
-    pub dim: i32,
-}
 
-impl Config {
-    pub fn new() -> Config {
-        Config {
-            inner: 6,
-            outer: 6,
-            ratio: 55,
-            main: 1,
-            tile: TileType::Left,
-            pad: false,
-            monocle: false,
-            smart_h: None,
-            smart_v: None,
-            dim: 0,
-        }
-    }
+    fn ranged_inc(existing: u32, value: u32, field: Field) -> u32 {
+        let max = limits(field).1 as u32;
 
-    fn ranged_inc(existing: u32, value: u32, max: u32) -> u32 {
         if existing + value > max {
             return max;
         }
         existing + value
     }
 
-    fn ranged_iinc(existing: i32, value: u32, max: i32) -> i32 {
+    fn ranged_iinc(existing: i32, value: u32, field: Field) -> i32 {
+        let max = limits(field).1;
         let value = value as i32;
 
         if existing + value > max {
@@ -350,14 +1504,17 @@ impl Config {
         existing + value
     }
 
-    fn ranged_dec(existing: u32, value: u32, min: u32) -> u32 {
+    fn ranged_dec(existing: u32, value: u32, field: Field) -> u32 {
+        let min = limits(field).0 as u32;
+
         if value > existing - min {
             return min;
         }
         existing - value
     }
 
-    fn ranged_idec(existing: i32, value: u32, min: i32) -> i32 {
+    fn ranged_idec(existing: i32, value: u32, field: Field) -> i32 {
+        let min = limits(field).0;
         let value = value as i32;
 
         if value > existing - min {
@@ -366,7 +1523,9 @@ impl Config {
         existing - value
     }
 
-    fn ranged_set(value: u32, min: u32, max: u32) -> u32 {
+    fn ranged_set(value: i32, field: Field) -> i32 {
+        let (min, max) = limits(field);
+
         if value < min {
             return min;
         }
@@ -379,99 +1538,127 @@ impl Config {
     }
 
     pub fn inc_inner(&mut self, value: u32) {
-        self.inner = Config::ranged_inc(self.inner, value, 1024);
+        self.inner = Config::ranged_inc(self.inner, value, Field::Inner);
     }
 
     pub fn inc_outer(&mut self, value: u32) {
-        self.outer = Config::ranged_inc(self.outer, value, 1024);
+        self.outer = Config::ranged_inc(self.outer, value, Field::Outer);
     }
 
     pub fn inc_smart_h(&mut self, value: u32) {
         self.smart_h = match self.smart_h {
-            Some(v) => Some(Config::ranged_inc(v, value, 1024)),
-            None => Some(Config::ranged_inc(self.inner + self.outer, value, 1024)),
+            Some(v) => Some(Config::ranged_inc(v, value, Field::SmartH)),
+            None => Some(Config::ranged_inc(
+                self.inner + self.outer,
+                value,
+                Field::SmartH,
+            )),
         }
     }
 
     pub fn inc_smart_v(&mut self, value: u32) {
         self.smart_v = match self.smart_v {
-            Some(v) => Some(Config::ranged_inc(v, value, 1024)),
-            None => Some(Config::ranged_inc(self.inner + self.outer, value, 1024)),
+            Some(v) => Some(Config::ranged_inc(v, value, Field::SmartV)),
+            None => Some(Config::ranged_inc(
+                self.inner + self.outer,
+                value,
+                Field::SmartV,
+            )),
         }
     }
 
     pub fn inc_ratio(&mut self, value: u32) {
-        self.ratio = Config::ranged_inc(self.ratio, value, 90);
+        self.ratio = Config::ranged_inc(self.ratio, value, Field::Ratio);
     }
 
     pub fn inc_main(&mut self, value: u32) {
-        self.main = Config::ranged_inc(self.main, value, 16);
+        self.main = Config::ranged_inc(self.main, value, Field::Main);
     }
 
     pub fn inc_dim(&mut self, value: u32) {
-        self.dim = Config::ranged_iinc(self.dim, value, 100);
+        self.dim = Config::ranged_iinc(self.dim, value, Field::Dim);
+    }
+
+    pub fn inc_zoom_ratio(&mut self, value: u32) {
+        self.zoom_ratio = Config::ranged_inc(self.zoom_ratio, value, Field::ZoomRatio);
     }
 
     pub fn dec_inner(&mut self, value: u32) {
-        self.inner = Config::ranged_dec(self.inner, value, 0);
+        self.inner = Config::ranged_dec(self.inner, value, Field::Inner);
     }
 
     pub fn dec_outer(&mut self, value: u32) {
-        self.outer = Config::ranged_dec(self.outer, value, 0);
+        self.outer = Config::ranged_dec(self.outer, value, Field::Outer);
     }
 
     pub fn dec_smart_h(&mut self, value: u32) {
         self.smart_h = match self.smart_h {
-            Some(v) => Some(Config::ranged_dec(v, value, 0)),
-            None => Some(Config::ranged_dec(self.inner + self.outer, value, 0)),
+            Some(v) => Some(Config::ranged_dec(v, value, Field::SmartH)),
+            None => Some(Config::ranged_dec(
+                self.inner + self.outer,
+                value,
+                Field::SmartH,
+            )),
         }
     }
 
     pub fn dec_smart_v(&mut self, value: u32) {
         self.smart_v = match self.smart_v {
-            Some(v) => Some(Config::ranged_dec(v, value, 0)),
-            None => Some(Config::ranged_dec(self.inner + self.outer, value, 0)),
+            Some(v) => Some(Config::ranged_dec(v, value, Field::SmartV)),
+            None => Some(Config::ranged_dec(
+                self.inner + self.outer,
+                value,
+                Field::SmartV,
+            )),
         }
     }
 
     pub fn dec_ratio(&mut self, value: u32) {
-        self.ratio = Config::ranged_dec(self.ratio, value, 10);
+        self.ratio = Config::ranged_dec(self.ratio, value, Field::Ratio);
     }
 
     pub fn dec_main(&mut self, value: u32) {
-        self.main = Config::ranged_dec(self.main, value, 1);
+        self.main = Config::ranged_dec(self.main, value, Field::Main);
     }
 
     pub fn dec_dim(&mut self, value: u32) {
-        self.dim = Config::ranged_idec(self.dim, value, -100);
+        self.dim = Config::ranged_idec(self.dim, value, Field::Dim);
+    }
+
+    pub fn dec_zoom_ratio(&mut self, value: u32) {
+        self.zoom_ratio = Config::ranged_dec(self.zoom_ratio, value, Field::ZoomRatio);
+    }
+
+    pub fn set_inner(&mut self, value: i32) {
+        self.inner = Config::ranged_set(value, Field::Inner) as u32;
     }
 
-    pub fn set_inner(&mut self, value: u32) {
-        self.inner = Config::ranged_set(value, 0, 1024);
+    pub fn set_outer(&mut self, value: i32) {
+        self.outer = Config::ranged_set(value, Field::Outer) as u32;
     }
 
-    pub fn set_outer(&mut self, value: u32) {
-        self.outer = Config::ranged_set(value, 0, 1024);
+    pub fn set_smart_h(&mut self, value: i32) {
+        self.smart_h = Some(Config::ranged_set(value, Field::SmartH) as u32)
     }
 
-    pub fn set_smart_h(&mut self, value: u32) {
-        self.smart_h = Some(Config::ranged_set(value, 0, 1024))
+    pub fn set_smart_v(&mut self, value: i32) {
+        self.smart_v = Some(Config::ranged_set(value, Field::SmartV) as u32)
     }
 
-    pub fn set_smart_v(&mut self, value: u32) {
-        self.smart_v = Some(Config::ranged_set(value, 0, 1024))
+    pub fn set_ratio(&mut self, value: i32) {
+        self.ratio = Config::ranged_set(value, Field::Ratio) as u32;
     }
 
-    pub fn set_ratio(&mut self, value: u32) {
-        self.ratio = Config::ranged_set(value, 10, 90);
+    pub fn set_main(&mut self, value: i32) {
+        self.main = Config::ranged_set(value, Field::Main) as u32;
     }
 
-    pub fn set_main(&mut self, value: u32) {
-        self.main = Config::ranged_set(value, 1, 16);
+    pub fn set_dim(&mut self, value: i32) {
+        self.dim = Config::ranged_set(value, Field::Dim);
     }
 
-    pub fn set_dim(&mut self, value: u32) {
-        self.dim = Config::ranged_set(value, 0, 100) as i32;
+    pub fn set_zoom_ratio(&mut self, value: i32) {
+        self.zoom_ratio = Config::ranged_set(value, Field::ZoomRatio) as u32;
     }
 }
 
@@ -501,10 +1688,10 @@ mod tests {
         assert_eq!(storage.build(Some(32), Some("HD-1")).inner, 16);
         assert_eq!(storage.build(Some(32), None).inner, 16);
 
-        // now a single monitor
+        // now a single monitor, which doesn't override the tag
         storage.apply_with(None, Some("HD-1"), |c| c.inner = 8);
 
-        assert_eq!(storage.build(Some(32), Some("HD-1")).inner, 8);
+        assert_eq!(storage.build(Some(32), Some("HD-1")).inner, 16);
         assert_eq!(storage.build(None, Some("HD-1")).inner, 8);
 
         // finally, override everything we monitor-tag combination
@@ -513,4 +1700,865 @@ mod tests {
         assert_eq!(storage.build(Some(32), Some("HD-1")).inner, 4);
         assert_eq!(storage.build(Some(4), Some("HD-1")).inner, 8);
     }
+
+    #[test]
+    fn it_replaces_older_entries() {
+        let mut storage = ConfigStorage::new();
+
+        for i in 0..10 {
+            storage.apply_with(Some(1), None, |c| c.inner = i);
+            storage.apply_with(Some(1), None, |c| c.outer = i);
+        }
+
+        assert_eq!(storage.entries[&None].len(), 2);
+        assert_eq!(storage.build(Some(1), None).inner, 9);
+    }
+
+    #[test]
+    fn it_rebuilds_cached_configs() {
+        let mut storage = ConfigStorage::new();
+
+        storage
+            .apply_cmd(None, Some("laptop"), "view-padding 2")
+            .unwrap();
+        storage
+            .apply_cmd(Some(1), Some("eDP-1"), "main-ratio 60 --ephemeral")
+            .unwrap();
+
+        assert_eq!(storage.build(Some(1), Some("eDP-1")).inner, 6);
+        assert_eq!(storage.build(Some(1), Some("eDP-1")).ratio, 60);
+        assert!(storage
+            .cache
+            .borrow()
+            .contains_key(&(Some(1), Some("eDP-1".to_string()))));
+
+        // aliases
+        storage
+            .apply_cmd(None, None, "output-alias laptop eDP-1")
+            .unwrap();
+        assert_eq!(storage.build(Some(1), Some("eDP-1")).inner, 2);
+
+        // cascading
+        storage
+            .apply_cmd(None, None, "view-padding 4 --cascade")
+            .unwrap();
+        assert_eq!(storage.build(Some(1), Some("eDP-1")).inner, 4);
+
+        // ephemeral settings
+        storage.clear_ephemeral(1, "eDP-1");
+        assert_eq!(storage.build(Some(1), Some("eDP-1")).ratio, 55);
+
+        // precedence
+        storage
+            .apply_cmd(None, Some("eDP-1"), "main-ratio 40")
+            .unwrap();
+        storage.apply_cmd(Some(1), None, "main-ratio 70").unwrap();
+        assert_eq!(storage.build(Some(1), Some("eDP-1")).ratio, 70);
+
+        storage
+            .apply_cmd(None, Some("eDP-1"), "main-ratio 45")
+            .unwrap();
+        assert_eq!(storage.build(Some(1), Some("eDP-1")).ratio, 70);
+        storage.apply_cmd(None, None, "precedence order").unwrap();
+        assert_eq!(storage.build(Some(1), Some("eDP-1")).ratio, 45);
+    }
+
+    #[test]
+    fn it_cascades() {
+        let mut storage = ConfigStorage::new();
+
+        storage.apply_cmd(Some(1), None, "view-padding 8").unwrap();
+        storage
+            .apply_cmd(Some(1), Some("HD-1"), "view-padding 12")
+            .unwrap();
+        storage
+            .apply_cmd(None, Some("HD-2"), "view-padding 16")
+            .unwrap();
+        storage
+            .apply_cmd(None, Some("HD-2"), "outer-padding 16")
+            .unwrap();
+        storage
+            .apply_cmd(Some(2), Some("HD-1"), "main-ratio 60")
+            .unwrap();
+
+        // cascading from a tag only touches that tag
+        storage
+            .apply_cmd(Some(1), None, "view-padding 2 --cascade")
+            .unwrap();
+
+        assert_eq!(storage.build(Some(1), Some("HD-1")).inner, 2);
+        assert_eq!(storage.build(None, Some("HD-2")).inner, 16);
+
+        // and from the top, everything
+        storage
+            .apply_cmd(None, None, "--cascade view-padding 4")
+            .unwrap();
+
+        assert_eq!(storage.build(Some(1), Some("HD-1")).inner, 4);
+        assert_eq!(storage.build(Some(1), Some("HD-2")).inner, 4);
+        assert_eq!(storage.build(None, Some("HD-2")).inner, 4);
+
+        // other fields stay as they were
+        assert_eq!(storage.build(None, Some("HD-2")).outer, 16);
+        assert_eq!(storage.build(Some(2), Some("HD-1")).ratio, 60);
+    }
+
+    #[test]
+    fn it_cascades_to_matching_outputs() {
+        let mut storage = ConfigStorage::new();
+
+        storage
+            .apply_cmd(None, Some("DP-1"), "view-padding 8")
+            .unwrap();
+        storage
+            .apply_cmd(Some(1), Some("DP-2"), "view-padding 8")
+            .unwrap();
+        storage
+            .apply_cmd(None, Some("HD-1"), "view-padding 8")
+            .unwrap();
+
+        storage
+            .apply_cmd(None, Some("DP-*"), "view-padding 2 --cascade")
+            .unwrap();
+
+        assert_eq!(storage.build(None, Some("DP-1")).inner, 2);
+        assert_eq!(storage.build(Some(1), Some("DP-2")).inner, 2);
+        assert_eq!(storage.build(None, Some("HD-1")).inner, 8);
+    }
+
+    #[test]
+    fn it_cascades_past_anything_that_would_win() {
+        let mut storage = ConfigStorage::new();
+
+        storage
+            .apply_cmd(None, None, "output-alias laptop eDP-1")
+            .unwrap();
+        storage
+            .apply_cmd(None, Some("laptop"), "view-padding 8")
+            .unwrap();
+        storage.apply_cmd(Some(1), None, "view-padding 12").unwrap();
+        storage
+            .apply_cmd(Some(2), Some("HD-1"), "view-padding 16")
+            .unwrap();
+        storage
+            .apply_cmd(None, None, "--priority 1 outer-padding 20")
+            .unwrap();
+
+        storage
+            .apply_cmd(None, Some("eDP-*"), "view-padding 2 --cascade")
+            .unwrap();
+        storage
+            .apply_cmd(None, Some("eDP-*"), "outer-padding 4 --cascade")
+            .unwrap();
+
+        // the aliased output, the tag, and the higher priority are all gone
+        let config = storage.build(Some(1), Some("eDP-1"));
+        assert_eq!((config.inner, config.outer), (2, 4));
+
+        // but what couldn't win over it on those outputs stays
+        assert_eq!(storage.build(Some(2), Some("HD-1")).inner, 16);
+    }
+
+    #[test]
+    fn it_locks_scopes() {
+        let mut storage = ConfigStorage::new();
+
+        storage.apply_cmd(Some(4), None, "main-ratio 60").unwrap();
+        storage.apply_cmd(Some(4), None, "lock").unwrap();
+
+        // the tag, and the tag on every output, are left alone
+        assert_eq!(
+            Err(ApplyError::Locked {
+                tags: Some(4),
+                output: None
+            }),
+            storage.apply_cmd(Some(4), None, "main-ratio 70")
+        );
+
+        assert!(storage
+            .apply_cmd(Some(4), Some("HD-1"), "main-ratio +5")
+            .is_err());
+
+        // unless it wouldn't change anything
+        storage.apply_cmd(Some(4), None, "main-ratio 60").unwrap();
+
+        assert_eq!(storage.build(Some(4), None).ratio, 60);
+        assert_eq!(storage.build(Some(4), Some("HD-1")).ratio, 60);
+
+        // but other tags aren't
+        storage
+            .apply_cmd(Some(2), Some("HD-1"), "main-ratio 70")
+            .unwrap();
+        assert_eq!(storage.build(Some(2), Some("HD-1")).ratio, 70);
+
+        // and cascading skips locked scopes
+        storage
+            .apply_cmd(Some(2), Some("HD-1"), "main-ratio 65")
+            .unwrap();
+        storage
+            .apply_cmd(None, None, "main-ratio 50 --cascade")
+            .unwrap();
+
+        assert_eq!(storage.build(Some(2), Some("HD-1")).ratio, 50);
+        assert_eq!(storage.build(Some(4), None).ratio, 60);
+
+        // unless forced
+        storage
+            .apply_cmd(Some(4), None, "main-ratio 80 --force")
+            .unwrap();
+        assert_eq!(storage.build(Some(4), None).ratio, 80);
+
+        // rules and presets are covered too
+        storage
+            .apply_cmd(Some(4), None, "when views > 2 main-count 2 --force")
+            .unwrap();
+        assert!(storage.apply_cmd(Some(4), None, "clear-rules").is_err());
+        assert!(storage
+            .apply_cmd(Some(4), None, "preset-save demo")
+            .is_err());
+        assert_eq!(storage.rules.len(), 1);
+        assert!(storage.presets.is_empty());
+
+        storage.apply_cmd(Some(4), None, "unlock").unwrap();
+        storage.apply_cmd(Some(4), None, "main-ratio 40").unwrap();
+        assert_eq!(storage.build(Some(4), None).ratio, 40);
+    }
+
+    #[test]
+    fn it_keeps_locked_scopes_from_broader_changes() {
+        let mut storage = ConfigStorage::new();
+
+        storage.apply_cmd(Some(1), None, "lock").unwrap();
+
+        storage.apply_cmd(None, None, "main-ratio +10").unwrap();
+        assert_eq!(storage.build(Some(1), None).ratio, 55);
+        assert_eq!(storage.build(Some(2), None).ratio, 65);
+
+        storage
+            .apply_cmd(None, None, "--priority 2 main-ratio 30")
+            .unwrap();
+        storage.apply_cmd(None, None, "precedence order").unwrap();
+        storage.apply_cmd(None, None, "main-ratio 80").unwrap();
+        assert_eq!(storage.build(Some(1), None).ratio, 55);
+        assert_eq!(storage.build(Some(2), None).ratio, 80);
+
+        // unless forced
+        storage
+            .apply_cmd(None, None, "--priority 3 main-ratio 70 --force")
+            .unwrap();
+        assert_eq!(storage.build(Some(1), None).ratio, 70);
+    }
+
+    #[test]
+    fn it_clears_ephemeral_settings() {
+        let mut storage = ConfigStorage::new();
+
+        storage.apply_cmd(Some(1), None, "pad on").unwrap();
+        storage
+            .apply_cmd(Some(1), Some("HD-1"), "monocle on --ephemeral")
+            .unwrap();
+        storage
+            .apply_cmd(Some(1), None, "diminish 20 --ephemeral")
+            .unwrap();
+        storage
+            .apply_cmd(Some(1), Some("HD-1"), "main-ratio 60 --ephemeral")
+            .unwrap();
+        storage
+            .apply_cmd(Some(2), Some("HD-1"), "monocle on --ephemeral")
+            .unwrap();
+
+        storage.clear_ephemeral(1, "HD-1");
+
+        let config = storage.build(Some(1), Some("HD-1"));
+        assert!(config.pad);
+        assert!(!config.monocle);
+        assert_eq!(config.ratio, 55);
+
+        assert!(storage.build(Some(2), Some("HD-1")).monocle);
+
+        // every output has its own tag 1, so one for all of them stays
+        assert_eq!(config.dim, 20);
+
+        // setting it again without the flag makes it stick
+        storage
+            .apply_cmd(Some(2), Some("HD-1"), "monocle on --ephemeral")
+            .unwrap();
+        storage
+            .apply_cmd(Some(2), Some("HD-1"), "monocle on")
+            .unwrap();
+        storage.clear_ephemeral(2, "HD-1");
+
+        assert!(storage.build(Some(2), Some("HD-1")).monocle);
+    }
+
+    #[test]
+    fn it_applies_rules() {
+        let mut storage = ConfigStorage::new();
+
+        let params = |view_count| Params {
+            view_count,
+            usable_width: 1920,
+            usable_height: 1080,
+        };
+
+        storage
+            .apply_cmd(None, None, "when views > 4 main-count 2")
+            .unwrap();
+        storage
+            .apply_cmd(None, None, "when views > 4 diminish 30")
+            .unwrap();
+        storage
+            .apply_cmd(Some(1), None, "when views == 2 main-ratio 50")
+            .unwrap();
+        storage.apply_cmd(Some(1), None, "main-ratio 60").unwrap();
+
+        let config = storage.build_with(Some(1), None, &params(5));
+        assert_eq!((config.main, config.dim, config.ratio), (2, 30, 60));
+
+        let config = storage.build_with(Some(1), None, &params(2));
+        assert_eq!((config.main, config.dim, config.ratio), (1, 0, 50));
+
+        let config = storage.build_with(Some(2), None, &params(2));
+        assert_eq!(config.ratio, 55);
+
+        // rules don't change what the scope itself is set to
+        assert_eq!(storage.build(Some(1), None).ratio, 60);
+
+        // the more specific rule goes last
+        storage
+            .apply_cmd(Some(1), Some("HD-1"), "when views > 1 main-count 3")
+            .unwrap();
+        storage
+            .apply_cmd(None, None, "when views > 1 main-count 4")
+            .unwrap();
+
+        assert_eq!(
+            storage.build_with(Some(1), Some("HD-1"), &params(5)).main,
+            3
+        );
+
+        // newer rules replace older ones for the same setting
+        storage
+            .apply_cmd(None, None, "when views > 4 'main-count' 5")
+            .unwrap();
+        assert_eq!(storage.build_with(Some(2), None, &params(5)).main, 5);
+        assert_eq!(storage.rules.len(), 5);
+
+        storage.apply_cmd(None, None, "clear-rules").unwrap();
+        assert_eq!(storage.build_with(Some(2), None, &params(5)).main, 1);
+        assert_eq!(
+            storage.build_with(Some(1), Some("HD-1"), &params(5)).main,
+            3
+        );
+
+        // and dimensions work the same way
+        storage
+            .apply_cmd(
+                Some(3),
+                Some("HD-1"),
+                "when height > width main-location top",
+            )
+            .unwrap();
+        storage
+            .apply_cmd(Some(3), None, "when width >= 3000 main-ratio 40")
+            .unwrap();
+
+        let portrait = Params {
+            view_count: 2,
+            usable_width: 1080,
+            usable_height: 1920,
+        };
+
+        let wide = Params {
+            view_count: 2,
+            usable_width: 3440,
+            usable_height: 1440,
+        };
+
+        assert_eq!(
+            storage.build_with(Some(3), Some("HD-1"), &portrait).tile,
+            TileType::Top
+        );
+        assert_eq!(
+            storage.build_with(Some(3), Some("HD-2"), &portrait).tile,
+            TileType::Left
+        );
+        assert_eq!(
+            storage.build_with(Some(3), Some("HD-1"), &wide).tile,
+            TileType::Left
+        );
+        assert_eq!(storage.build_with(Some(3), Some("HD-1"), &wide).ratio, 40);
+
+        storage
+            .apply_cmd(Some(3), Some("HD-1"), "clear-rules")
+            .unwrap();
+        storage.apply_cmd(Some(3), None, "clear-rules").unwrap();
+
+        // only real commands make it in
+        assert_eq!(
+            Err(ApplyError::UnknownCommand {
+                name: "free-ice-cream".to_string(),
+                suggestion: None
+            }),
+            storage.apply_cmd(None, None, "when views > 4 free-ice-cream 2")
+        );
+        assert_eq!(storage.rules.len(), 2);
+    }
+
+    #[test]
+    fn it_applies_configs_in_order() {
+        let mut storage = ConfigStorage::new();
+
+        storage.apply_cmd(None, None, "precedence order").unwrap();
+
+        storage.apply_with(Some(32), None, |c| c.inner = 16);
+        storage.apply_with(None, Some("HD-1"), |c| c.inner = 8);
+
+        assert_eq!(storage.build(Some(32), Some("HD-1")).inner, 8);
+
+        storage.apply_with(Some(32), None, |c| c.inner = 12);
+
+        assert_eq!(storage.build(Some(32), Some("HD-1")).inner, 12);
+    }
+
+    #[test]
+    fn it_suggests_near_matches() {
+        let mut storage = ConfigStorage::new();
+
+        assert_eq!(
+            Err(ApplyError::UnknownCommand {
+                name: "view-paddding".to_string(),
+                suggestion: Some("view-padding".to_string())
+            }),
+            storage.apply_cmd(None, None, "view-paddding 8")
+        );
+
+        assert_eq!(
+            Err(ApplyError::InvalidValue {
+                value: "lef".to_string(),
+                suggestion: Some("left".to_string())
+            }),
+            storage.apply_cmd(None, None, "main-location lef")
+        );
+
+        assert_eq!(
+            Err(ApplyError::InvalidValue {
+                value: "lowst".to_string(),
+                suggestion: Some("lowest".to_string())
+            }),
+            storage.apply_cmd(None, None, "multi-tag-policy lowst")
+        );
+
+        assert_eq!(
+            Err(ApplyError::UnknownCommand {
+                name: "preset-lst".to_string(),
+                suggestion: Some("preset-list".to_string())
+            }),
+            storage.apply_cmd(None, None, "preset-lst")
+        );
+
+        assert_eq!(
+            Err(ApplyError::ReservedName("preset-list".to_string())),
+            storage.add_alias("preset-list", "flip")
+        );
+
+        storage
+            .apply_cmd(None, None, "preset-save presenting")
+            .unwrap();
+
+        assert_eq!(
+            Err(ApplyError::UnknownPreset {
+                name: "presnting".to_string(),
+                suggestion: Some("presenting".to_string())
+            }),
+            storage.apply_cmd(None, None, "preset-load presnting")
+        );
+    }
+
+    #[test]
+    fn it_rotates() {
+        let mut config = Config::new();
+        config.set_ratio(60);
+
+        let mut rotate = |direction| {
+            config.update(Command::Textual {
+                namespace: "rotate",
+                value: direction,
+            });
+            config.tile
+        };
+
+        assert_eq!(TileType::Top, rotate("cw"));
+        assert_eq!(TileType::Right, rotate("cw"));
+        assert_eq!(TileType::Bottom, rotate("cw"));
+        assert_eq!(TileType::Left, rotate("cw"));
+        assert_eq!(TileType::Bottom, rotate("ccw"));
+        assert_eq!(TileType::Right, rotate("ccw"));
+
+        // the split moves with the main area
+        config.update(parse_command("move-split-left 5").unwrap());
+        assert_eq!(config.ratio, 65);
+        assert!(!config.update(parse_command("rotate left").unwrap()));
+    }
+
+    #[test]
+    fn it_zooms() {
+        let mut storage = ConfigStorage::new();
+        for cmd in [
+            "main-count 3",
+            "main-ratio 40",
+            "main-location top",
+            "zoom-ratio 75",
+        ] {
+            storage.apply_cmd(Some(1), None, cmd).unwrap();
+        }
+
+        storage.apply_cmd(Some(1), None, "zoom").unwrap();
+        let config = storage.build(Some(1), None);
+        assert_eq!(
+            (config.main, config.ratio, config.tile),
+            (1, 75, TileType::Top)
+        );
+
+        // other scopes aren't zoomed
+        storage.apply_cmd(Some(2), None, "zoom").unwrap();
+        storage.apply_cmd(Some(2), None, "zoom").unwrap();
+        assert_eq!(storage.build(Some(2), None).ratio, 55);
+
+        storage
+            .apply_cmd(Some(1), None, "main-location left")
+            .unwrap();
+        storage.apply_cmd(Some(1), None, "zoom").unwrap();
+        let config = storage.build(Some(1), None);
+        assert_eq!(
+            (config.main, config.ratio, config.tile),
+            (3, 40, TileType::Top)
+        );
+
+        // a zoom that's refused isn't remembered
+        storage.apply_cmd(Some(1), None, "lock").unwrap();
+        assert!(storage.apply_cmd(Some(1), None, "zoom").is_err());
+        storage.apply_cmd(Some(1), None, "unlock").unwrap();
+        storage.apply_cmd(Some(1), None, "zoom").unwrap();
+        assert_eq!(storage.build(Some(1), None).main, 1);
+
+        storage.apply_cmd(None, None, "zoom-ratio 95").unwrap();
+        assert_eq!(storage.build(None, None).zoom_ratio, 90);
+
+        // a zoom that's been undone some other way zooms in again
+        storage
+            .apply_cmd(Some(4), Some("HD-1"), "zoom --ephemeral")
+            .unwrap();
+        storage.clear_ephemeral(4, "HD-1");
+        storage
+            .apply_cmd(Some(4), Some("HD-1"), "main-count 3")
+            .unwrap();
+        storage.apply_cmd(Some(4), Some("HD-1"), "zoom").unwrap();
+        let config = storage.build(Some(4), Some("HD-1"));
+        assert_eq!((config.main, config.ratio), (1, 90));
+    }
+
+    #[test]
+    fn it_goes_back_to_the_last_layout() {
+        let mut storage = ConfigStorage::new();
+
+        let layout = |storage: &mut ConfigStorage, cmd| {
+            storage.apply_cmd(Some(1), None, cmd).unwrap();
+            Layout::of(&storage.build(Some(1), None))
+        };
+
+        // nothing to go back to yet
+        let left = layout(&mut storage, "last-layout");
+        assert_eq!(left.tile, TileType::Left);
+
+        layout(&mut storage, "main-location bottom");
+        let top = layout(&mut storage, "main-location top");
+
+        // settings that aren't part of the arrangement don't count
+        layout(&mut storage, "main-ratio 70");
+
+        assert_eq!(layout(&mut storage, "last-layout").tile, TileType::Bottom);
+        assert_eq!(layout(&mut storage, "last-layout"), top);
+
+        let monocle = layout(&mut storage, "monocle on");
+        layout(&mut storage, "main-count 2");
+        assert_eq!(layout(&mut storage, "last-layout"), monocle);
+        assert_eq!(layout(&mut storage, "last-layout").main, 2);
+
+        // each scope keeps its own
+        storage
+            .apply_cmd(Some(2), None, "main-location right")
+            .unwrap();
+        storage.apply_cmd(Some(2), None, "last-layout").unwrap();
+        assert_eq!(storage.build(Some(2), None).tile, TileType::Left);
+        assert_eq!(layout(&mut storage, "last-layout"), monocle);
+    }
+
+    #[test]
+    fn it_cycles_through_values() {
+        let mut storage = ConfigStorage::new();
+
+        let ratio = |storage: &mut ConfigStorage, tags| {
+            storage
+                .apply_cmd(tags, None, "cycle main-ratio 50 60 70")
+                .unwrap();
+            storage.build(tags, None).ratio
+        };
+
+        assert_eq!(50, ratio(&mut storage, Some(1)));
+        assert_eq!(60, ratio(&mut storage, Some(1)));
+
+        // each scope keeps its own place
+        assert_eq!(50, ratio(&mut storage, Some(2)));
+        assert_eq!(70, ratio(&mut storage, Some(1)));
+        assert_eq!(50, ratio(&mut storage, Some(1)));
+
+        // another list for the same setting starts from its own beginning
+        storage
+            .apply_cmd(Some(1), None, "toggle main-ratio 30 80")
+            .unwrap();
+        assert_eq!(30, storage.build(Some(1), None).ratio);
+        assert_eq!(60, ratio(&mut storage, Some(1)));
+
+        storage
+            .apply_cmd(None, None, "toggle main-location top 'right'")
+            .unwrap();
+        assert_eq!(storage.build(None, None).tile, TileType::Top);
+
+        storage
+            .apply_cmd(None, None, "toggle main-location top 'right'")
+            .unwrap();
+        assert_eq!(storage.build(None, None).tile, TileType::Right);
+
+        // a value that doesn't apply doesn't move things along
+        storage.apply_cmd(Some(3), None, "lock").unwrap();
+
+        assert!(storage
+            .apply_cmd(Some(3), None, "toggle view-padding 0 12")
+            .is_err());
+
+        storage.apply_cmd(Some(3), None, "unlock").unwrap();
+        storage
+            .apply_cmd(
+                Some(3),
+                Some("HD-1"),
+                "toggle view-padding 0 12 --ephemeral",
+            )
+            .unwrap();
+
+        assert_eq!(storage.build(Some(3), Some("HD-1")).inner, 0);
+
+        storage.clear_ephemeral(3, "HD-1");
+        assert_eq!(storage.build(Some(3), Some("HD-1")).inner, 6);
+    }
+
+    #[test]
+    fn it_clamps_values_out_of_range() {
+        let mut storage = ConfigStorage::new();
+
+        storage.apply_cmd(None, None, "main-ratio 95").unwrap();
+        storage
+            .apply_cmd(None, None, "when views > 2 main-count 20")
+            .unwrap();
+
+        assert_eq!(storage.build(None, None).ratio, 90);
+        assert_eq!(
+            vec![
+                ApplyError::OutOfRange {
+                    value: 95,
+                    min: 10,
+                    max: 90
+                },
+                ApplyError::OutOfRange {
+                    value: 20,
+                    min: 1,
+                    max: 16
+                }
+            ],
+            storage.take_warnings()
+        );
+
+        // adjusting past the limit isn't worth a warning
+        storage.apply_cmd(None, None, "main-ratio +50").unwrap();
+        storage.apply_cmd(None, None, "diminish =-100").unwrap();
+        storage.apply_cmd(None, None, "move-split-left 5").unwrap();
+
+        let config = storage.build(None, None);
+        assert_eq!(config.ratio, 85);
+        assert_eq!(config.dim, -100);
+        assert!(storage.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn it_applies_priorities() {
+        let mut storage = ConfigStorage::new();
+
+        storage
+            .apply_cmd(None, Some("HD-1"), "--priority 1 view-padding 8")
+            .unwrap();
+        storage
+            .apply_cmd(Some(32), None, "view-padding 16")
+            .unwrap();
+
+        assert_eq!(storage.build(Some(32), Some("HD-1")).inner, 8);
+        assert_eq!(storage.build(Some(32), Some("HD-2")).inner, 16);
+
+        // the combination still loses to a higher priority
+        storage
+            .apply_cmd(Some(32), Some("HD-1"), "view-padding 4")
+            .unwrap();
+
+        assert_eq!(storage.build(Some(32), Some("HD-1")).inner, 8);
+
+        // even when the value is what's already in effect
+        storage
+            .apply_cmd(None, Some("HD-1"), "--priority 1 main-ratio 55")
+            .unwrap();
+        storage.apply_cmd(Some(1), None, "main-ratio 70").unwrap();
+
+        assert_eq!(storage.build(Some(1), Some("HD-1")).ratio, 55);
+    }
+
+    #[test]
+    fn it_matches_output_patterns() {
+        assert_eq!(output_specificity("DP-1", "DP-1"), Some(3));
+        assert_eq!(output_specificity("DP-*", "DP-5"), Some(2));
+        assert_eq!(output_specificity("DP-?", "DP-12"), None);
+        assert_eq!(output_specificity("DP-1,HDMI-A-1", "HDMI-A-1"), Some(3));
+        assert_eq!(output_specificity("DP-1,HDMI-*", "HDMI-A-1"), Some(2));
+        assert_eq!(output_specificity("!eDP-1", "DP-1"), Some(1));
+        assert_eq!(output_specificity("!eDP-1", "eDP-1"), None);
+        assert_eq!(output_specificity("DP-*,!DP-2", "DP-2"), None);
+        assert_eq!(output_specificity("DP-*,!DP-2", "DP-3"), Some(2));
+    }
+
+    #[test]
+    fn it_applies_specific_outputs_last() {
+        let mut storage = ConfigStorage::new();
+
+        storage.apply_with(None, Some("DP-1"), |c| c.inner = 1);
+        storage.apply_with(None, Some("DP-*"), |c| c.inner = 2);
+        storage.apply_with(None, Some("!eDP-1"), |c| c.inner = 3);
+
+        assert_eq!(storage.build(None, Some("DP-1")).inner, 1);
+        assert_eq!(storage.build(None, Some("DP-7")).inner, 2);
+        assert_eq!(storage.build(None, Some("HDMI-A-1")).inner, 3);
+        assert_eq!(storage.build(None, Some("eDP-1")).inner, 6);
+    }
+
+    #[test]
+    fn it_resolves_output_aliases() {
+        let mut storage = ConfigStorage::new();
+
+        storage
+            .apply_cmd(None, None, "output-alias laptop eDP-*")
+            .unwrap();
+        storage
+            .apply_cmd(None, Some("laptop"), "view-padding 1")
+            .unwrap();
+        storage
+            .apply_cmd(None, Some("!laptop"), "view-padding 2")
+            .unwrap();
+
+        assert_eq!(storage.build(None, Some("eDP-2")).inner, 1);
+        assert_eq!(storage.build(None, Some("DP-1")).inner, 2);
+
+        // changing the alias re-targets what's already there
+        storage
+            .apply_cmd(None, None, "output-alias laptop DP-1,DP-2")
+            .unwrap();
+
+        assert_eq!(storage.build(None, Some("eDP-2")).inner, 2);
+        assert_eq!(storage.build(None, Some("DP-1")).inner, 1);
+        assert_eq!(storage.build(None, Some("DP-3")).inner, 2);
+    }
+
+    #[test]
+    fn it_stores_policies_per_output() {
+        let mut storage = ConfigStorage::new();
+
+        assert_eq!(storage.policy("HD-1"), MultiTagPolicy::Last);
+
+        storage
+            .apply_cmd(Some(1), None, "multi-tag-policy lowest")
+            .unwrap();
+        storage
+            .apply_cmd(Some(1), Some("HD-1"), "multi-tag-policy highest")
+            .unwrap();
+
+        assert_eq!(storage.policy("HD-1"), MultiTagPolicy::Highest);
+        assert_eq!(storage.policy("HD-2"), MultiTagPolicy::Lowest);
+    }
+
+    #[test]
+    fn it_names_tags() {
+        let mut storage = ConfigStorage::new();
+
+        storage.apply_cmd(None, None, "tag-name 7 scratch").unwrap();
+        assert_eq!(storage.tag_names().get("scratch"), Some(&7));
+
+        // renaming replaces the old name
+        storage.apply_cmd(None, None, "tag-name 7 pad").unwrap();
+        assert_eq!(storage.tag_names().get("scratch"), None);
+        assert_eq!(storage.tag_names().get("pad"), Some(&7));
+
+        // and show up in labels for the tags they name
+        storage.apply_cmd(None, None, "tag-name 1 web").unwrap();
+        assert_eq!(storage.tag_label(1 << 6), Some("pad".to_string()));
+        assert_eq!(storage.tag_label(0b1000011), Some("web,2,pad".to_string()));
+        assert_eq!(storage.tag_label(2), None);
+
+        // numbers can't be names, and tags only go so high
+        assert_eq!(
+            Err(ApplyError::InvalidValue {
+                value: "2 3".to_string(),
+                suggestion: None
+            }),
+            storage.apply_cmd(None, None, "tag-name 2 3")
+        );
+        assert!(storage.apply_cmd(None, None, "tag-name 33 big").is_err());
+
+        // and names have to fit in a list of tags
+        assert!(storage.apply_cmd(None, None, "tag-name 3 a,b").is_err());
+        assert!(storage.apply_cmd(None, None, "tag-name 3 'a b'").is_err());
+        assert_eq!(storage.tag_names().len(), 2);
+    }
+
+    #[test]
+    fn it_saves_and_loads_presets() {
+        let mut storage = ConfigStorage::new();
+
+        storage.apply_cmd(Some(1), None, "main-ratio 60").unwrap();
+        storage.apply_cmd(Some(1), None, "view-padding 2").unwrap();
+        storage
+            .apply_cmd(Some(1), None, "preset-save coding")
+            .unwrap();
+
+        // load it somewhere else
+        storage
+            .apply_cmd(Some(2), Some("HD-1"), "preset-load coding")
+            .unwrap();
+
+        let config = storage.build(Some(2), Some("HD-1"));
+        assert_eq!(config.ratio, 60);
+        assert_eq!(config.inner, 2);
+
+        // the rest of that output is left alone
+        assert_eq!(storage.build(Some(4), Some("HD-1")).ratio, 55);
+
+        // and settings the preset shares with broader scopes stay put
+        storage.apply_cmd(None, None, "outer-padding 20").unwrap();
+        assert_eq!(storage.build(Some(2), Some("HD-1")).outer, 6);
+        assert_eq!(storage.build(Some(4), Some("HD-1")).outer, 20);
+
+        // and unknown presets change nothing
+        assert_eq!(
+            Err(ApplyError::UnknownPreset {
+                name: "presenting".to_string(),
+                suggestion: None
+            }),
+            storage.apply_cmd(Some(3), None, "preset-load presenting")
+        );
+        assert_eq!(storage.build(Some(3), None).ratio, 55);
+    }
 }
@@ -1,44 +1,160 @@
 mod config;
 mod parse;
+mod rule;
+mod suggest;
 mod tile;
 
-use config::ConfigStorage;
-use parse::{parse_output, parse_tags, split_commands};
+use config::{ApplyError, ConfigStorage, MultiTagPolicy};
+use parse::{
+    parse_output, parse_scope, parse_tags, parse_tagset, split_alias, split_alias_call,
+    split_commands, with_options,
+};
 use river_layout_toolkit::{run, GeneratedLayout, Layout, Rectangle};
-use std::{convert::Infallible, env, iter};
+use std::{collections::HashMap, env, error::Error, fmt, fs, iter, process};
 use tile::{flip, rotate, LeftPrimary, Monocle, Padded, Params, Tile, TileType};
 
 use crate::parse::AllOrOne;
 
 fn main() {
-    let all_args: Vec<String> = env::args().collect();
+    let mut all_args: Vec<String> = env::args().collect();
 
     if all_args.len() > 1 && (all_args[1] == "-version" || all_args[1] == "--version") {
         println!("{}", env!("CARGO_PKG_VERSION"));
         return;
     }
 
-    let mut layout = FilTile {
-        tag_log: TagLog::new(),
-        configs: ConfigStorage::new(),
-    };
+    if all_args.len() > 1 && all_args[1] == "--check" {
+        process::exit(check("args", &all_args[2..].join(" ")));
+    }
+
+    if all_args.len() > 1 && all_args[1] == "--check-file" {
+        let Some(path) = all_args.get(2) else {
+            eprintln!("--check-file needs a path");
+            process::exit(2);
+        };
+
+        match fs::read_to_string(path) {
+            Ok(cmds) => process::exit(check(path, &cmds)),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                process::exit(2);
+            }
+        }
+    }
+
+    // exit if any of the startup commands fail
+    let strict = all_args.len() > 1 && all_args[1] == "--strict";
+
+    if strict {
+        all_args.remove(1);
+    }
+
+    let mut layout = FilTile::new();
 
     let call_string = all_args[1..].join(" ").trim().to_string();
 
-    if !call_string.is_empty() {
-        let _ = layout.user_cmd(call_string, None, "all");
+    if !call_string.is_empty() && layout.user_cmd(call_string, None, "all").is_err() && strict {
+        process::exit(1);
     }
 
     run(layout).unwrap();
 }
 
+// Run commands against a layout that's thrown away, without talking to River,
+// and report everything that failed. Returns the exit code.
+fn check(name: &str, cmds: &str) -> i32 {
+    let errors = check_errors(cmds);
+
+    for e in &errors {
+        let (line, column) = line_and_column(cmds, e.position());
+        eprintln!("{}:{}:{}: {}", name, line, column, e);
+    }
+
+    if errors.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+// Everything wrong with the commands, in order, including the commands of every
+// alias they define, whether it's called or not.
+fn check_errors(cmds: &str) -> Vec<CommandError> {
+    let mut layout = FilTile::new();
+    layout.definitions = Some(Vec::new());
+
+    let mut errors = layout.run_cmds(cmds, None, "all");
+
+    // each alias is tried on its own copy, once all the others are known
+    for (offset, body) in layout.definitions.take().unwrap_or_default() {
+        for mut e in layout.clone().run_cmds(&body, None, "all") {
+            e.offset += offset;
+            errors.push(e);
+        }
+    }
+
+    // anything that had to be clamped is a mistake here
+    for e in errors.iter_mut() {
+        e.warning = false;
+    }
+
+    errors.sort_by_key(|e| e.position());
+    errors
+}
+
+// both counted from 1
+fn line_and_column(s: &str, position: usize) -> (usize, usize) {
+    let before = &s[..position.min(s.len())];
+
+    match before.rfind('\n') {
+        Some(i) => (before.matches('\n').count() + 1, position - i),
+        None => (1, position + 1),
+    }
+}
+
+#[derive(Clone)]
 struct FilTile {
     tag_log: TagLog,
     configs: ConfigStorage,
+    // the alias definitions seen and where their commands start, when checking
+    definitions: Option<Vec<(usize, String)>>,
 }
 
+// A command that couldn't be applied, along with the command itself and where
+// it starts in everything that was sent. Warnings are for commands that were
+// still applied, like ones with values that had to be clamped.
+#[derive(Clone, Debug)]
+struct CommandError {
+    cmd: String,
+    offset: usize,
+    error: ApplyError,
+    warning: bool,
+}
+
+impl CommandError {
+    // where the problem is in everything that was sent
+    fn position(&self) -> usize {
+        match &self.error {
+            ApplyError::Parse(e) => self.offset + e.position,
+            _ => self.offset,
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.warning {
+            write!(f, "warning: command \"{}\": {}", self.cmd, self.error)
+        } else {
+            write!(f, "invalid command \"{}\": {}", self.cmd, self.error)
+        }
+    }
+}
+
+impl Error for CommandError {}
+
 impl Layout for FilTile {
-    type Error = Infallible;
+    type Error = CommandError;
 
     const NAMESPACE: &'static str = "filtile";
 
@@ -49,39 +165,20 @@ impl Layout for FilTile {
         output: &str,
     ) -> Result<(), Self::Error> {
         if let Some(t) = tags {
-            self.tag_log.record_tags(t);
+            self.tag_log.record_tags(output, t);
         }
 
-        let output = match output {
-            "all" => None,
-            _ => Some(output),
-        };
-
-        let (cmd, cdr) = split_commands(&cmd);
-
-        let tags = match parse_tags(cmd) {
-            Some(t) => match t {
-                AllOrOne::One(i) => Some(i),
-                AllOrOne::All => None,
-            },
-            None => self.tag_log.last_tag,
-        };
-
-        let output = match parse_output(cmd) {
-            Some(o) => match o {
-                AllOrOne::One(s) => Some(s),
-                AllOrOne::All => None,
-            },
-            None => output,
-        };
-
-        self.configs.apply_cmd(tags, output, cmd);
+        // keep going after a failed command, but report the first failure
+        let errors = self.run_cmds(&cmd, tags, output);
 
-        if let Some(remaining) = cdr {
-            let _ = self.user_cmd(remaining.to_string(), None, output.unwrap_or("all"));
+        for e in &errors {
+            eprintln!("{}", e);
         }
 
-        Ok(())
+        match errors.into_iter().find(|e| !e.warning) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
     }
 
     fn generate_layout(
@@ -92,9 +189,14 @@ impl Layout for FilTile {
         tags: u32,
         output: &str,
     ) -> Result<GeneratedLayout, Self::Error> {
-        self.tag_log.record_tags(tags);
+        self.tag_log.record_tags(output, tags);
 
-        let config = self.configs.build(self.tag_log.last_tag, Some(output));
+        // an empty tag starts over, but only if it's the one we're looking at
+        if view_count == 0
+            && self.tag_log.resolve(output, tags, MultiTagPolicy::Default) == Some(tags)
+        {
+            self.configs.clear_ephemeral(tags, output);
+        }
 
         let params = Params {
             view_count,
@@ -102,6 +204,10 @@ impl Layout for FilTile {
             usable_height,
         };
 
+        let policy = self.configs.policy(output);
+        let resolved = self.tag_log.resolve(output, tags, policy);
+        let config = self.configs.build_with(resolved, Some(output), &params);
+
         let base: Box<dyn Tile> = Box::new(LeftPrimary::new(
             config.inner,
             config.outer,
@@ -155,8 +261,14 @@ impl Layout for FilTile {
             tile = Box::new(Padded::new(tile, h, v));
         }
 
+        // named tags show up next to the layout
+        let name = match resolved.and_then(|t| self.configs.tag_label(t)) {
+            Some(label) => format!("{} {}", name, label),
+            None => name.to_string(),
+        };
+
         let mut layout = GeneratedLayout {
-            layout_name: name.to_string(),
+            layout_name: name,
             views: Vec::with_capacity(view_count as usize),
         };
 
@@ -173,40 +285,623 @@ impl Layout for FilTile {
     }
 }
 
-// Keep track of the last "single" tag we see, so that we can store and
-// recall configs not based on combinations.
+impl FilTile {
+    fn new() -> FilTile {
+        FilTile {
+            tag_log: TagLog::new(),
+            configs: ConfigStorage::new(),
+            definitions: None,
+        }
+    }
+
+    // Apply every command in a string, returning the ones that failed. An
+    // --output carries over to the commands after it.
+    fn run_cmds(&mut self, cmds: &str, visible: Option<u32>, current: &str) -> Vec<CommandError> {
+        let mut errors = Vec::new();
+        let mut remaining = Some(cmds);
+        let mut chained = None;
+
+        while let Some(cmd) = remaining {
+            let (car, cdr) = split_commands(cmd);
+            let offset = car.as_ptr() as usize - cmds.as_ptr() as usize;
+
+            // an alias has commands of its own, so it has to be split first
+            if let Some((definition, rest)) = split_alias(&cmds[offset..]) {
+                let result = definition
+                    .map_err(ApplyError::from)
+                    .and_then(|(name, body)| {
+                        self.configs.add_alias(name, body)?;
+
+                        if let Some(definitions) = &mut self.definitions {
+                            let start = body.as_ptr() as usize - cmds.as_ptr() as usize;
+                            definitions.push((start, body.to_string()));
+                        }
+
+                        Ok(())
+                    });
+
+                if let Err(error) = result {
+                    errors.push(CommandError {
+                        cmd: car.to_string(),
+                        offset,
+                        error,
+                        warning: false,
+                    });
+                }
+
+                remaining = rest;
+                continue;
+            }
+
+            if let Err(error) = self.run_cmd(car, visible, current, chained, &mut Vec::new()) {
+                errors.push(CommandError {
+                    cmd: car.to_string(),
+                    offset,
+                    error,
+                    warning: false,
+                });
+            }
+
+            // the same warning for every tag is only worth one
+            let mut warnings = self.configs.take_warnings();
+            warnings.dedup();
+
+            for error in warnings {
+                errors.push(CommandError {
+                    cmd: car.to_string(),
+                    offset,
+                    error,
+                    warning: true,
+                });
+            }
+
+            if let Ok(Some(output)) = parse_output(car) {
+                chained = match output {
+                    AllOrOne::One(o) => Some(o),
+                    AllOrOne::All => Some("all"),
+                };
+            }
+
+            remaining = cdr;
+        }
+
+        errors
+    }
+
+    // Apply a single command, or every command of an alias, with the options
+    // it was sent with added to each. Keeps going after a failure, but returns
+    // the first one.
+    fn run_cmd(
+        &mut self,
+        cmd: &str,
+        visible: Option<u32>,
+        current: &str,
+        chained: Option<&str>,
+        expanding: &mut Vec<String>,
+    ) -> Result<(), ApplyError> {
+        let (name, body, options) = match split_alias_call(cmd) {
+            Some((name, options)) => match self.configs.alias(name) {
+                Some(body) => (name.to_string(), body.clone(), options),
+                None => return self.apply_cmd(cmd, visible, current, chained),
+            },
+            None => return self.apply_cmd(cmd, visible, current, chained),
+        };
+
+        if expanding.contains(&name) {
+            return Err(ApplyError::RecursiveAlias(name));
+        }
+
+        expanding.push(name.clone());
+
+        let mut result = Ok(());
+        let mut remaining = Some(body.as_str());
+
+        while let Some(cmds) = remaining {
+            let (car, cdr) = split_commands(cmds);
+            let expanded = with_options(car, &options);
+
+            let r = self
+                .run_cmd(&expanded, visible, current, chained, expanding)
+                .map_err(|error| ApplyError::Alias {
+                    name: name.clone(),
+                    cmd: car.to_string(),
+                    error: Box::new(error),
+                });
+
+            result = result.and(r);
+            remaining = cdr;
+        }
+
+        expanding.pop();
+        result
+    }
+
+    // Apply a single command, sent while the given tags were visible on the
+    // given output, after a command in the same list with the chained --output.
+    fn apply_cmd(
+        &mut self,
+        cmd: &str,
+        visible: Option<u32>,
+        current: &str,
+        chained: Option<&str>,
+    ) -> Result<(), ApplyError> {
+        let policy = self.configs.policy(current);
+
+        // whatever isn't given explicitly falls back to the scope
+        let explicit = parse_scope(cmd)?;
+        let scope = explicit.unwrap_or(self.configs.default_scope());
+
+        let current_tags = match visible {
+            Some(v) => self.tag_log.resolve(current, v, policy),
+            None => self.tag_log.last_tag(current),
+        };
+
+        let default_tags = if scope.has_tags() { current_tags } else { None };
+
+        let default_output = match (chained, current) {
+            (Some("all"), _) if explicit.is_none() => None,
+            (Some(o), _) if explicit.is_none() => Some(o),
+            (_, "all") => None,
+            _ if scope.has_output() => Some(current),
+            _ => None,
+        };
+
+        let names = self.configs.tag_names();
+
+        let tagset = parse_tagset(cmd, names)?;
+
+        let tags = if let Some(t) = tagset {
+            vec![Some(t)]
+        } else {
+            match parse_tags(cmd, names)? {
+                Some(t) => match t {
+                    AllOrOne::One(list) => list.into_iter().map(Some).collect(),
+                    AllOrOne::All => vec![None],
+                },
+                None => vec![default_tags],
+            }
+        };
+
+        let output = match parse_output(cmd)? {
+            Some(o) => match o {
+                AllOrOne::One(s) => Some(s),
+                AllOrOne::All => None,
+            },
+            None => default_output,
+        };
+
+        for t in tags {
+            self.configs.apply_cmd(t, output, cmd)?;
+        }
+
+        // a combination only gets its own config once something is set for it
+        if let Some(t) = tagset {
+            self.tag_log.add_tagset(t);
+        }
+
+        Ok(())
+    }
+}
+
+// Keep track of the last "single" tag we see on each output, so that we can
+// store and recall configs not based on combinations. Combinations only get
+// their own configs once they've been opted into with --tagset.
+#[derive(Clone)]
 struct TagLog {
-    pub last_tag: Option<u32>,
+    last_tags: HashMap<String, u32>,
     single_tags: Vec<u32>,
+    tagsets: Vec<u32>,
 }
 
 impl TagLog {
     pub fn new() -> TagLog {
         TagLog {
-            last_tag: None,
+            last_tags: HashMap::new(),
             single_tags: (0..31).map(|i| 1 << i).chain(iter::once(0)).collect(),
+            tagsets: Vec::new(),
         }
     }
 
-    pub fn record_tags(&mut self, tag: u32) {
+    pub fn last_tag(&self, output: &str) -> Option<u32> {
+        self.last_tags.get(output).copied()
+    }
+
+    pub fn record_tags(&mut self, output: &str, tag: u32) {
         if self.single_tags.contains(&tag) {
-            self.last_tag = Some(tag);
+            self.last_tags.insert(output.to_string(), tag);
+        }
+    }
+
+    pub fn add_tagset(&mut self, tags: u32) {
+        if !self.tagsets.contains(&tags) {
+            self.tagsets.push(tags);
+        }
+    }
+
+    // which tags should we look up configs for, given what's visible?
+    pub fn resolve(&self, output: &str, tags: u32, policy: MultiTagPolicy) -> Option<u32> {
+        if self.tagsets.contains(&tags) || self.single_tags.contains(&tags) {
+            return Some(tags);
+        }
+
+        match policy {
+            MultiTagPolicy::Last => self.last_tag(output),
+            MultiTagPolicy::Lowest => Some(1 << tags.trailing_zeros()),
+            MultiTagPolicy::Highest => Some(1 << (31 - tags.leading_zeros())),
+            MultiTagPolicy::Default => None,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::TagLog;
+    use crate::tile::TileType;
+    use crate::{check_errors, line_and_column, ApplyError, FilTile, MultiTagPolicy, TagLog};
+    use river_layout_toolkit::Layout;
 
     #[test]
     fn it_logs_single_tags() {
         let mut log = TagLog::new();
 
-        log.record_tags(512);
-        log.record_tags(14);
-        log.record_tags(12);
+        log.record_tags("HD-1", 512);
+        log.record_tags("HD-1", 14);
+        log.record_tags("HD-1", 12);
+
+        assert_eq!(Some(512), log.last_tag("HD-1"));
+    }
+
+    #[test]
+    fn it_logs_tags_per_output() {
+        let mut log = TagLog::new();
+
+        log.record_tags("HD-1", 1);
+        log.record_tags("HD-2", 2);
+        log.record_tags("HD-1", 3);
+        log.record_tags("HD-2", 4);
+
+        assert_eq!(Some(1), log.last_tag("HD-1"));
+        assert_eq!(Some(4), log.last_tag("HD-2"));
+        assert_eq!(None, log.last_tag("HD-3"));
+    }
+
+    #[test]
+    fn it_resolves_tagsets() {
+        let mut log = TagLog::new();
+
+        log.record_tags("HD-1", 1);
+        log.record_tags("HD-1", 3);
+
+        assert_eq!(Some(1), log.resolve("HD-1", 3, MultiTagPolicy::Last));
+
+        log.add_tagset(3);
+
+        assert_eq!(Some(3), log.resolve("HD-1", 3, MultiTagPolicy::Last));
+        assert_eq!(Some(1), log.resolve("HD-1", 5, MultiTagPolicy::Last));
+        assert_eq!(Some(1), log.last_tag("HD-1"));
+    }
+
+    #[test]
+    fn it_resolves_with_policies() {
+        let mut log = TagLog::new();
+
+        log.record_tags("HD-1", 8);
+
+        assert_eq!(Some(8), log.resolve("HD-1", 6, MultiTagPolicy::Last));
+        assert_eq!(Some(2), log.resolve("HD-1", 6, MultiTagPolicy::Lowest));
+        assert_eq!(Some(4), log.resolve("HD-1", 6, MultiTagPolicy::Highest));
+        assert_eq!(None, log.resolve("HD-1", 6, MultiTagPolicy::Default));
+
+        // single tags aren't affected
+        assert_eq!(Some(16), log.resolve("HD-1", 16, MultiTagPolicy::Default));
+    }
+
+    #[test]
+    fn it_only_keeps_tagsets_that_were_set() {
+        let mut layout = FilTile::new();
+
+        assert!(layout
+            .user_cmd("--tagset 1,2 bogus".into(), None, "all")
+            .is_err());
+        assert_eq!(
+            None,
+            layout.tag_log.resolve("HD-1", 3, MultiTagPolicy::Default)
+        );
+
+        layout
+            .user_cmd("--tagset 1,2 main-count 2".into(), None, "all")
+            .unwrap();
+        assert_eq!(
+            Some(3),
+            layout.tag_log.resolve("HD-1", 3, MultiTagPolicy::Default)
+        );
+    }
+
+    #[test]
+    fn it_applies_to_the_default_scope() {
+        let mut layout = FilTile::new();
+
+        // the current tag and output by default
+        layout
+            .user_cmd("view-padding 1".into(), Some(2), "HD-1")
+            .unwrap();
 
-        assert_eq!(Some(512), log.last_tag);
+        assert_eq!(layout.configs.build(Some(2), Some("HD-1")).inner, 1);
+        assert_eq!(layout.configs.build(Some(2), Some("HD-2")).inner, 6);
+
+        layout
+            .user_cmd("default-scope output".into(), None, "all")
+            .unwrap();
+        layout
+            .user_cmd("view-padding 2".into(), Some(4), "HD-1")
+            .unwrap();
+
+        assert_eq!(layout.configs.build(Some(8), Some("HD-1")).inner, 2);
+        assert_eq!(layout.configs.build(Some(4), Some("HD-2")).inner, 6);
+
+        // explicit options still win
+        layout
+            .user_cmd("--tags 3 view-padding 3".into(), Some(4), "HD-1")
+            .unwrap();
+
+        assert_eq!(layout.configs.build(Some(4), Some("HD-1")).inner, 3);
+        assert_eq!(layout.configs.build(Some(4), Some("HD-2")).inner, 6);
+
+        // and so does an explicit scope
+        layout
+            .user_cmd("--scope global view-padding 4".into(), Some(4), "HD-1")
+            .unwrap();
+
+        assert_eq!(layout.configs.build(Some(8), Some("HD-2")).inner, 4);
+    }
+
+    #[test]
+    fn it_carries_the_output_to_chained_commands() {
+        let mut layout = FilTile::new();
+
+        layout
+            .user_cmd(
+                "--output HD-1 view-padding 1, view-padding 2, --output all outer-padding 3, view-padding 4"
+                    .into(),
+                None,
+                "all",
+            )
+            .unwrap();
+
+        assert_eq!(layout.configs.build(None, Some("HD-1")).inner, 2);
+        assert_eq!(layout.configs.build(None, Some("HD-2")).inner, 4);
+        assert_eq!(layout.configs.build(None, Some("HD-2")).outer, 3);
+
+        // but not to the next time commands are sent
+        layout
+            .user_cmd("--output HD-1 pad on".into(), Some(1), "HD-2")
+            .unwrap();
+        layout.user_cmd("flip".into(), Some(1), "HD-2").unwrap();
+
+        assert_eq!(
+            layout.configs.build(Some(1), Some("HD-2")).tile,
+            TileType::Right
+        );
+    }
+
+    #[test]
+    fn it_applies_to_the_tag_of_the_output_sent_from() {
+        let mut layout = FilTile::new();
+
+        layout.generate_layout(1, 100, 100, 1, "HD-1").unwrap();
+        layout.generate_layout(1, 100, 100, 2, "HD-2").unwrap();
+
+        // River doesn't always send the tags along with a command
+        layout
+            .user_cmd("main-ratio 60".into(), None, "HD-1")
+            .unwrap();
+
+        assert_eq!(layout.configs.build(Some(1), Some("HD-1")).ratio, 60);
+        assert_eq!(layout.configs.build(Some(2), Some("HD-1")).ratio, 55);
+
+        layout.generate_layout(1, 100, 100, 4, "HD-2").unwrap();
+        layout
+            .user_cmd("main-ratio 40".into(), None, "HD-1")
+            .unwrap();
+        layout
+            .user_cmd("main-ratio 70".into(), None, "HD-2")
+            .unwrap();
+
+        assert_eq!(layout.configs.build(Some(1), Some("HD-1")).ratio, 40);
+        assert_eq!(layout.configs.build(Some(4), Some("HD-2")).ratio, 70);
+        assert_eq!(layout.configs.build(Some(2), Some("HD-2")).ratio, 55);
+    }
+
+    #[test]
+    fn it_clears_ephemeral_settings_on_empty_tags() {
+        let mut layout = FilTile::new();
+
+        layout.generate_layout(2, 100, 100, 1, "HD-1").unwrap();
+        layout
+            .user_cmd("monocle on --ephemeral".into(), Some(1), "HD-1")
+            .unwrap();
+
+        // another tag being empty doesn't matter
+        layout.generate_layout(0, 100, 100, 3, "HD-1").unwrap();
+        assert!(layout.configs.build(Some(1), Some("HD-1")).monocle);
+
+        layout.generate_layout(0, 100, 100, 1, "HD-1").unwrap();
+        assert!(!layout.configs.build(Some(1), Some("HD-1")).monocle);
+    }
+
+    #[test]
+    fn it_names_layouts_after_tags() {
+        let mut layout = FilTile::new();
+
+        layout
+            .user_cmd("tag-name 2 code".into(), None, "all")
+            .unwrap();
+
+        let generated = layout.generate_layout(1, 100, 100, 2, "HD-1").unwrap();
+        assert_eq!("← code", generated.layout_name);
+
+        let generated = layout.generate_layout(1, 100, 100, 4, "HD-1").unwrap();
+        assert_eq!("←", generated.layout_name);
+    }
+
+    #[test]
+    fn it_reports_failed_commands() {
+        let mut layout = FilTile::new();
+
+        layout.generate_layout(2, 100, 100, 1, "HD-1").unwrap();
+
+        // a bad tag doesn't fall back to the current one
+        let err = layout
+            .user_cmd("--tags 40 main-ratio 60".into(), None, "HD-1")
+            .unwrap_err();
+
+        assert_eq!("--tags 40 main-ratio 60", err.cmd);
+        assert_eq!(layout.configs.build(Some(1), Some("HD-1")).ratio, 55);
+
+        // the rest still run, but the first failure is returned
+        let err = layout
+            .user_cmd("main-ratio 60, nope, pad on, nah".into(), None, "HD-1")
+            .unwrap_err();
+
+        assert_eq!("nope", err.cmd);
+        assert_eq!(
+            ApplyError::UnknownCommand {
+                name: "nope".to_string(),
+                suggestion: None
+            },
+            err.error
+        );
+
+        let config = layout.configs.build(Some(1), Some("HD-1"));
+        assert_eq!(config.ratio, 60);
+        assert!(config.pad);
+    }
+
+    #[test]
+    fn it_expands_aliases() {
+        let mut layout = FilTile::new();
+
+        layout
+            .user_cmd(
+                "alias focus = monocle on, smart-padding 400, view-padding 0".into(),
+                None,
+                "all",
+            )
+            .unwrap();
+
+        layout
+            .user_cmd("focus --tags 3 --output HD-1".into(), None, "all")
+            .unwrap();
+
+        let config = layout.configs.build(Some(4), Some("HD-1"));
+        assert!(config.monocle);
+        assert_eq!(config.smart_h, Some(400));
+        assert_eq!(config.inner, 0);
+        assert!(!layout.configs.build(Some(4), None).monocle);
+
+        // aliases can use other aliases, and be defined along with other commands
+        layout
+            .user_cmd(
+                "pad on; alias both = 'focus, flip'; --tags 1 both".into(),
+                None,
+                "all",
+            )
+            .unwrap();
+
+        let config = layout.configs.build(Some(1), None);
+        assert!(config.monocle);
+        assert!(config.pad);
+        assert_eq!(config.tile, TileType::Right);
+
+        // a command's own tags win over the ones the alias is sent with
+        layout
+            .user_cmd(
+                "alias mixed = '--tags 2 main-count 2, main-count 3'".into(),
+                None,
+                "all",
+            )
+            .unwrap();
+        layout
+            .user_cmd("mixed --tagset 5".into(), None, "all")
+            .unwrap();
+
+        assert_eq!(layout.configs.build(Some(2), None).main, 2);
+        assert_eq!(layout.configs.build(Some(16), None).main, 3);
+    }
+
+    #[test]
+    fn it_stops_recursive_aliases() {
+        let mut layout = FilTile::new();
+
+        layout
+            .user_cmd("alias a = pad on, b\nalias b = a".into(), None, "all")
+            .unwrap();
+
+        let err = layout.user_cmd("a".into(), None, "all").unwrap_err();
+
+        assert_eq!(
+            "in alias a, \"b\": in alias b, \"a\": alias a uses itself",
+            err.error.to_string()
+        );
+        assert!(layout.configs.build(None, None).pad);
+
+        let err = layout
+            .user_cmd("alias flip = pad".into(), None, "all")
+            .unwrap_err();
+
+        assert_eq!(ApplyError::ReservedName("flip".to_string()), err.error);
+    }
+
+    #[test]
+    fn it_finds_where_commands_failed() {
+        let mut layout = FilTile::new();
+        let cmds = "view-padding 4\n# comments are fine\nmain-ratio 95; flip --tgas 1";
+        let errors = layout.run_cmds(cmds, None, "all");
+
+        assert_eq!(2, errors.len());
+        assert_eq!("main-ratio 95", errors[0].cmd);
+        assert!(errors[0].warning);
+        assert_eq!((3, 1), line_and_column(cmds, errors[0].position()));
+        assert_eq!("flip --tgas 1", errors[1].cmd);
+        assert!(!errors[1].warning);
+        assert_eq!((3, 21), line_and_column(cmds, errors[1].position()));
+
+        let config = layout.configs.build(None, None);
+        assert_eq!((config.inner, config.ratio), (4, 90));
+
+        // only check treats a clamped value as a failure
+        let mut layout = FilTile::new();
+        layout
+            .user_cmd("--tags 1,2 main-ratio 95".into(), None, "all")
+            .unwrap();
+
+        let errors = layout.run_cmds("--tags 1,2 main-ratio 95", None, "all");
+        assert_eq!(1, errors.len());
+        assert_eq!(
+            "warning: command \"--tags 1,2 main-ratio 95\": 95 is out of range (10 to 90)",
+            errors[0].to_string()
+        );
+    }
+
+    #[test]
+    fn it_checks_aliases_that_are_never_called() {
+        let cmds = "pad on\nalias focus = monocle on, main-location lef\nalias later = focus";
+        let errors = check_errors(cmds);
+
+        assert_eq!(2, errors.len());
+        assert_eq!("main-location lef", errors[0].cmd);
+        assert_eq!((2, 27), line_and_column(cmds, errors[0].position()));
+        assert_eq!("focus", errors[1].cmd);
+        assert_eq!((3, 15), line_and_column(cmds, errors[1].position()));
+
+        // using one defined further down is fine, and quoted ones work too
+        let cmds = "alias a = 'b, view-padding 4'\nalias b = main-ratio 95\na";
+        let errors = check_errors(cmds);
+        let positions: Vec<_> = errors
+            .iter()
+            .map(|e| line_and_column(cmds, e.position()))
+            .collect();
+
+        assert_eq!(vec![(1, 12), (2, 11), (3, 1)], positions);
+        assert!(errors.iter().all(|e| !e.warning));
     }
 }
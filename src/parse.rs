@@ -1,231 +1,876 @@
-#[derive(PartialEq, Debug)]
+use std::{collections::HashMap, fmt, iter};
+
+use crate::{
+    rule::{Comparison, Condition, Operand, Subject},
+    suggest::closest,
+};
+
+// options that don't take a value
+const FLAGS: [&str; 3] = ["--cascade", "--force", "--ephemeral"];
+
+// options that do
+const OPTIONS: [&str; 6] = [
+    "--tags",
+    "--tagmask",
+    "--tagset",
+    "--output",
+    "--priority",
+    "--scope",
+];
+
+// everything "--scope" and "default-scope" accept
+pub const SCOPES: [&str; 8] = [
+    "tag",
+    "current-tag",
+    "output",
+    "current-output",
+    "tag+output",
+    "current",
+    "global",
+    "all",
+];
+
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Command<'a> {
-    Invalid,
     Single(&'a str),
     Numeric {
         namespace: &'a str,
         operation: Operation,
-        value: u32,
+        value: i32,
     },
     Textual {
         namespace: &'a str,
         value: &'a str,
     },
+    Keyed {
+        namespace: &'a str,
+        key: &'a str,
+        value: &'a str,
+    },
 }
 
-#[derive(PartialEq, Debug)]
+impl<'a> Command<'a> {
+    pub fn namespace(&self) -> &'a str {
+        match *self {
+            Command::Single(namespace) => namespace,
+            Command::Numeric { namespace, .. } => namespace,
+            Command::Textual { namespace, .. } => namespace,
+            Command::Keyed { namespace, .. } => namespace,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Operation {
     Add,
     Subtract,
     Set,
 }
 
+// Why a command couldn't be parsed, and where, in bytes from the start of the
+// command.
+#[derive(PartialEq, Clone, Debug)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub position: usize,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum ParseErrorKind {
+    MissingCommand,
+    UnterminatedQuote,
+    UnknownOption(String),
+    MissingValue(String),
+    InvalidValue {
+        option: String,
+        value: String,
+        suggestion: Option<String>,
+    },
+    InvalidNumber(String),
+    TooManyArguments,
+    Usage(&'static str),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::MissingCommand => write!(f, "missing command"),
+            ParseErrorKind::UnterminatedQuote => write!(f, "unterminated quote"),
+            ParseErrorKind::UnknownOption(o) => write!(f, "unknown option {}", o),
+            ParseErrorKind::MissingValue(o) => write!(f, "missing value for {}", o),
+            ParseErrorKind::InvalidValue { option, value, .. } => {
+                write!(f, "invalid value {} for {}", value, option)
+            }
+            ParseErrorKind::InvalidNumber(s) => write!(f, "invalid number {}", s),
+            ParseErrorKind::TooManyArguments => write!(f, "too many arguments"),
+            ParseErrorKind::Usage(usage) => write!(f, "expected {}", usage),
+        }?;
+
+        write!(f, " at column {}", self.position + 1)?;
+
+        match &self.kind {
+            ParseErrorKind::InvalidValue {
+                suggestion: Some(s),
+                ..
+            } => write!(f, ", did you mean {}?", s),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ParseError {
+    // guess at what an invalid value was supposed to be
+    fn suggest<'a, I>(mut self, candidates: impl Fn() -> I) -> ParseError
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        if let ParseErrorKind::InvalidValue {
+            value, suggestion, ..
+        } = &mut self.kind
+        {
+            *suggestion = value
+                .split(',')
+                .flat_map(|part| iter::once(part).chain(part.split('-')))
+                .find_map(|part| closest(part, candidates()))
+                .map(|s| s.to_string());
+        }
+
+        self
+    }
+}
+
+// A single word of a command. Quotes are left out of the text, but not the
+// position.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    pub position: usize,
+    pub quoted: bool,
+}
+
+impl Token<'_> {
+    fn end(&self) -> usize {
+        if self.quoted {
+            self.position + self.text.len() + 2
+        } else {
+            self.position + self.text.len()
+        }
+    }
+}
+
+// The words of a command, with the options pulled out.
+#[derive(Debug)]
+struct Statement<'a> {
+    words: Vec<Token<'a>>,
+    options: Vec<(&'a str, Token<'a>)>,
+    flags: Vec<&'a str>,
+}
+
+// Which of the current tags and output a command applies to, when they
+// aren't given explicitly.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Scope {
+    Tag,
+    Output,
+    TagOutput,
+    Global,
+}
+
+impl Scope {
+    pub fn has_tags(&self) -> bool {
+        *self == Scope::Tag || *self == Scope::TagOutput
+    }
+
+    pub fn has_output(&self) -> bool {
+        *self == Scope::Output || *self == Scope::TagOutput
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub enum AllOrOne<T> {
     All,
     One(T),
 }
 
-pub fn parse_output(cmd: &str) -> Option<AllOrOne<&str>> {
-    match find_option("--output", cmd) {
-        Some(s) => match s {
-            "all" => Some(AllOrOne::All),
-            _ => Some(AllOrOne::One(s)),
-        },
-        None => None,
+// break a single command into words, on any amount of whitespace, keeping
+// quoted strings together and dropping "#" comments
+pub fn tokenize(cmd: &str) -> Result<Vec<Token<'_>>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = cmd.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        if c == '#' {
+            for (_, c) in chars.by_ref() {
+                if c == '\n' {
+                    break;
+                }
+            }
+
+            continue;
+        }
+
+        if c == '"' || c == '\'' {
+            let end = chars.by_ref().find(|(_, d)| *d == c).map(|(i, _)| i);
+
+            match end {
+                Some(end) => tokens.push(Token {
+                    text: &cmd[start + 1..end],
+                    position: start,
+                    quoted: true,
+                }),
+                None => {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::UnterminatedQuote,
+                        position: start,
+                    })
+                }
+            }
+
+            continue;
+        }
+
+        let mut end = cmd.len();
+
+        while let Some((i, d)) = chars.peek() {
+            if d.is_whitespace() {
+                end = *i;
+                break;
+            }
+
+            chars.next();
+        }
+
+        tokens.push(Token {
+            text: &cmd[start..end],
+            position: start,
+            quoted: false,
+        });
     }
+
+    Ok(tokens)
 }
 
-pub fn parse_tags(cmd: &str) -> Option<AllOrOne<u32>> {
-    match find_option("--tags", cmd) {
-        Some(s) => match s {
-            "all" => Some(AllOrOne::All),
-            _ => match s.parse::<u32>() {
-                Ok(i) => Some(AllOrOne::One(i)),
-                _ => None,
-            },
-        },
-        None => None,
+fn parse_statement(cmd: &str) -> Result<Statement<'_>, ParseError> {
+    let mut statement = Statement {
+        words: Vec::new(),
+        options: Vec::new(),
+        flags: Vec::new(),
+    };
+
+    let mut tokens = tokenize(cmd)?.into_iter();
+
+    while let Some(token) = tokens.next() {
+        if token.quoted || !token.text.starts_with("--") {
+            statement.words.push(token);
+        } else if FLAGS.contains(&token.text) {
+            statement.flags.push(token.text);
+        } else if OPTIONS.contains(&token.text) {
+            match tokens.next() {
+                Some(value) if value.quoted || !value.text.starts_with("--") => {
+                    statement.options.push((token.text, value))
+                }
+                _ => {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::MissingValue(token.text.to_string()),
+                        position: token.position,
+                    })
+                }
+            }
+        } else {
+            return Err(ParseError {
+                kind: ParseErrorKind::UnknownOption(token.text.to_string()),
+                position: token.position,
+            });
+        }
     }
+
+    Ok(statement)
 }
 
-pub fn parse_command(cmd: &str) -> Command {
-    let parts: Vec<&str> = cmd.split(' ').collect();
+pub fn parse_output(cmd: &str) -> Result<Option<AllOrOne<&str>>, ParseError> {
+    parse_option("--output", cmd, |s| match s {
+        "all" => Some(AllOrOne::All),
+        _ => Some(AllOrOne::One(s)),
+    })
+}
 
-    // check for a Rivertile command
-    if parts.len() == 2 {
-        let command = parse_rivertile_command(parts[0], parts[1]);
+// every tag gets its own mask, since that's how configs are stored
+pub fn parse_tags(
+    cmd: &str,
+    names: &HashMap<String, u32>,
+) -> Result<Option<AllOrOne<Vec<u32>>>, ParseError> {
+    let mask = parse_option("--tagmask", cmd, |s| match s {
+        "all" => Some(AllOrOne::All),
+        _ => s.parse::<u32>().ok().map(|m| AllOrOne::One(split_mask(m))),
+    })?;
 
-        if command != Command::Invalid {
-            return command;
-        }
+    if mask.is_some() {
+        return Ok(mask);
     }
 
-    let parts = remove_options(&parts);
+    parse_option("--tags", cmd, |s| match s {
+        "all" => Some(AllOrOne::All),
+        _ => parse_tag_list(s, names).map(AllOrOne::One),
+    })
+    .map_err(|e| e.suggest(|| names.keys().map(|n| n.as_str())))
+}
 
-    if parts.len() == 1 {
-        return Command::Single(parts[0]);
-    };
+// a tagset is the combination of all the tags given, as a single mask
+pub fn parse_tagset(cmd: &str, names: &HashMap<String, u32>) -> Result<Option<u32>, ParseError> {
+    parse_option("--tagset", cmd, |s| {
+        parse_tag_list(s, names).map(|tags| tags.iter().fold(0, |acc, t| acc | t))
+    })
+    .map_err(|e| e.suggest(|| names.keys().map(|n| n.as_str())))
+}
 
-    if parts.len() == 2 {
-        let namespace = parts[0];
-        let mut value = parts[1];
+// "7", "1,3,5", "1-4", "web", or any mix of those
+fn parse_tag_list(s: &str, names: &HashMap<String, u32>) -> Option<Vec<u32>> {
+    let mut tags = Vec::new();
 
-        let operation = if value.starts_with('+') {
-            value = &value[1..];
-            Operation::Add
-        } else if value.starts_with('-') {
-            value = &value[1..];
-            Operation::Subtract
-        } else {
-            Operation::Set
+    for part in s.split(',') {
+        let (start, end) = match parse_tag_index(part, names) {
+            Some(index) => (index, index),
+            None => {
+                let (start, end) = part.split_once('-')?;
+                (parse_tag_index(start, names)?, parse_tag_index(end, names)?)
+            }
         };
 
-        let value: u32 = match value.parse() {
-            Ok(v) => v,
-            Err(_) => {
-                if let Ok(v) = value.parse::<f32>() {
-                    (v * 100.0) as u32
-                } else {
-                    return Command::Textual { namespace, value };
-                }
+        if start > end {
+            return None;
+        }
+
+        for index in start..=end {
+            let mask = 1 << (index - 1);
+
+            if !tags.contains(&mask) {
+                tags.push(mask);
             }
-        };
+        }
+    }
+
+    Some(tags)
+}
+
+fn parse_tag_index(s: &str, names: &HashMap<String, u32>) -> Option<u32> {
+    match s.parse::<u32>() {
+        Ok(i) if (1..=32).contains(&i) => Some(i),
+        Ok(_) => None,
+        Err(_) => names.get(s).copied(),
+    }
+}
 
-        return Command::Numeric {
+fn split_mask(mask: u32) -> Vec<u32> {
+    if mask == 0 {
+        return vec![0];
+    }
+
+    (0..32).map(|i| 1 << i).filter(|t| mask & t != 0).collect()
+}
+
+pub fn parse_scope(cmd: &str) -> Result<Option<Scope>, ParseError> {
+    parse_option("--scope", cmd, scope_from_str).map_err(|e| e.suggest(|| SCOPES))
+}
+
+pub fn scope_from_str(s: &str) -> Option<Scope> {
+    match s {
+        "tag" | "current-tag" => Some(Scope::Tag),
+        "output" | "current-output" => Some(Scope::Output),
+        "tag+output" | "current" => Some(Scope::TagOutput),
+        "global" | "all" => Some(Scope::Global),
+        _ => None,
+    }
+}
+
+pub fn parse_priority(cmd: &str) -> Result<Option<i32>, ParseError> {
+    parse_option("--priority", cmd, |s| s.parse::<i32>().ok())
+}
+
+pub fn parse_command(cmd: &str) -> Result<Command<'_>, ParseError> {
+    let words = parse_statement(cmd)?.words;
+
+    // check for a Rivertile command
+    if let [cmd, value] = words.as_slice() {
+        if let Some(command) = parse_rivertile_command(cmd.text, value.text) {
+            return Ok(command);
+        }
+    }
+
+    match words.as_slice() {
+        [] => Err(ParseError {
+            kind: ParseErrorKind::MissingCommand,
+            position: cmd.len(),
+        }),
+        [single] => Ok(Command::Single(single.text)),
+        [namespace, value] => parse_value(namespace.text, value),
+        // "diminish = -50"
+        [namespace, equals, value] if equals.text == "=" && !equals.quoted => {
+            match parse_number(value.text) {
+                Some(v) if !value.quoted => Ok(Command::Numeric {
+                    namespace: namespace.text,
+                    operation: Operation::Set,
+                    value: v,
+                }),
+                _ => Err(ParseError {
+                    kind: ParseErrorKind::InvalidNumber(value.text.to_string()),
+                    position: value.position,
+                }),
+            }
+        }
+        [namespace, key, value] => Ok(Command::Keyed {
+            namespace: namespace.text,
+            key: key.text,
+            value: value.text,
+        }),
+        [_, _, _, extra, ..] => Err(ParseError {
+            kind: ParseErrorKind::TooManyArguments,
+            position: extra.position,
+        }),
+    }
+}
+
+// "+5" adds, "-5" subtracts, and "5" or "=5" sets, which is the only way to
+// set a negative number ("=-5"). Anything else is text, unless it's quoted.
+fn parse_value<'a>(namespace: &'a str, value: &Token<'a>) -> Result<Command<'a>, ParseError> {
+    let text = value.text;
+
+    if value.quoted {
+        return Ok(Command::Textual {
+            namespace,
+            value: text,
+        });
+    }
+
+    let (operation, number) = match text.chars().next() {
+        Some('+') => (Operation::Add, &text[1..]),
+        Some('-') => (Operation::Subtract, &text[1..]),
+        Some('=') => (Operation::Set, &text[1..]),
+        _ => (Operation::Set, text),
+    };
+
+    let explicit = number.len() != text.len();
+
+    match parse_number(number) {
+        Some(v) if v >= 0 || text.starts_with('=') => Ok(Command::Numeric {
             namespace,
             operation,
-            value,
-        };
+            value: v,
+        }),
+        None if !explicit => Ok(Command::Textual {
+            namespace,
+            value: text,
+        }),
+        _ => Err(ParseError {
+            kind: ParseErrorKind::InvalidNumber(text.to_string()),
+            position: value.position,
+        }),
     }
+}
 
-    Command::Invalid
+// whole numbers as they are, fractions as a percentage
+fn parse_number(s: &str) -> Option<i32> {
+    match s.parse::<i32>() {
+        Ok(v) => Some(v),
+        Err(_) => s
+            .parse::<f32>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .map(|v| (v * 100.0) as i32),
+    }
 }
 
-fn parse_rivertile_command<'a>(cmd: &'a str, value: &'a str) -> Command<'a> {
-    let v = if cmd == "-main-ratio" {
-        if let Ok(v) = value.parse::<f32>() {
-            (v * 100.0) as u32
-        } else {
-            return Command::Invalid;
+// A setting and the values to step through for it, along with the options it
+// was sent with.
+#[derive(PartialEq, Debug)]
+pub struct Cycle<'a> {
+    pub setting: &'a str,
+    pub values: Vec<&'a str>,
+    pub options: String,
+}
+
+// "cycle main-ratio 50 60 70", or "toggle view-padding 0 12" with exactly two
+// values. Quoted values keep their quotes.
+pub fn parse_cycle(cmd: &str) -> Option<Result<Cycle<'_>, ParseError>> {
+    let words = parse_statement(cmd).ok()?.words;
+    let first = words.first().filter(|w| !w.quoted)?;
+
+    let usage = match first.text {
+        "toggle" if words.len() != 4 => "toggle [setting] [a] [b]",
+        "cycle" if words.len() < 3 => "cycle [setting] [values...]",
+        "toggle" | "cycle" => {
+            return Some(Ok(Cycle {
+                setting: words[1].text,
+                values: words[2..]
+                    .iter()
+                    .map(|w| &cmd[w.position..w.end()])
+                    .collect(),
+                options: without_words(cmd, &words),
+            }))
         }
+        _ => return None,
+    };
+
+    Some(Err(ParseError {
+        kind: ParseErrorKind::Usage(usage),
+        position: first.position,
+    }))
+}
+
+// "when views > 4 main-count 2" into the condition and the command
+pub fn parse_rule(cmd: &str) -> Option<(Condition, String)> {
+    let words = parse_statement(cmd).ok()?.words;
+
+    if words.len() < 5 || words[0].text != "when" || words[0].quoted {
+        return None;
+    }
+
+    let subject = parse_subject(words[1].text)?;
+
+    let comparison = match words[2].text {
+        "<" => Comparison::Less,
+        "<=" => Comparison::LessOrEqual,
+        "==" => Comparison::Equal,
+        "!=" => Comparison::NotEqual,
+        ">=" => Comparison::GreaterOrEqual,
+        ">" => Comparison::Greater,
+        _ => return None,
+    };
+
+    let value = match words[3].text.parse() {
+        Ok(v) => Operand::Value(v),
+        Err(_) => Operand::Subject(parse_subject(words[3].text)?),
+    };
+
+    let condition = Condition {
+        subject,
+        comparison,
+        value,
+    };
+
+    // keep the command as it was written, quotes and all
+    let start = words[4].position;
+    let end = words[words.len() - 1].end();
+
+    Some((condition, cmd[start..end].to_string()))
+}
+
+fn parse_subject(s: &str) -> Option<Subject> {
+    match s {
+        "views" => Some(Subject::Views),
+        "width" => Some(Subject::Width),
+        "height" => Some(Subject::Height),
+        _ => None,
+    }
+}
+
+fn parse_rivertile_command<'a>(cmd: &'a str, value: &'a str) -> Option<Command<'a>> {
+    let v = if cmd == "-main-ratio" {
+        (value.parse::<f32>().ok()? * 100.0) as i32
     } else if cmd == "-main-location" {
         0
-    } else if let Ok(v) = value.parse::<u32>() {
-        v
     } else {
-        return Command::Invalid;
+        value.parse::<i32>().ok().filter(|v| *v >= 0)?
+    };
+
+    let numeric = |namespace| Command::Numeric {
+        namespace,
+        operation: Operation::Set,
+        value: v,
     };
 
     match cmd {
-        "-view-padding" => Command::Numeric {
-            namespace: "view-padding",
-            operation: Operation::Set,
-            value: v,
-        },
-        "-outer-padding" => Command::Numeric {
-            namespace: "outer-padding",
-            operation: Operation::Set,
-            value: v,
-        },
-        "-main-location" => Command::Textual {
+        "-view-padding" => Some(numeric("view-padding")),
+        "-outer-padding" => Some(numeric("outer-padding")),
+        "-main-location" => Some(Command::Textual {
             namespace: "main-location",
             value,
-        },
-        "-main-count" => Command::Numeric {
-            namespace: "main-count",
-            operation: Operation::Set,
-            value: v,
-        },
-        "-main-ratio" => Command::Numeric {
-            namespace: "main-ratio",
-            operation: Operation::Set,
-            value: v,
-        },
-        _ => Command::Invalid,
+        }),
+        "-main-count" => Some(numeric("main-count")),
+        "-main-ratio" => Some(numeric("main-ratio")),
+        _ => None,
     }
 }
 
 // rip the first command off a string, leave the rest alone
 pub fn split_commands(cmd: &str) -> (&str, Option<&str>) {
+    let (car, cdr) = split_first(cmd);
+
+    match cdr {
+        // skip over anything empty, like a line that's only a comment
+        Some(rest) if is_empty(car) => split_commands(rest),
+        Some(rest) if is_empty(split_commands(rest).0) => (car, None),
+        _ => (car, cdr),
+    }
+}
+
+fn split_first(cmd: &str) -> (&str, Option<&str>) {
     let cmd = cmd.trim();
 
-    // check for Rivertile
-    let parts: Vec<&str> = if cmd.starts_with('-') && !cmd.starts_with("--") {
-        // split at the second whitespace
-        let mut lookback = ' ';
-        let mut split = 0;
-        let mut found = false;
-
-        for (i, c) in cmd.chars().enumerate() {
-            if c == ' ' && lookback != ' ' {
-                if split != 0 {
-                    split = i;
-                    found = true;
-                    break;
-                }
+    let split = if cmd.starts_with('-') && !cmd.starts_with("--") {
+        // Rivertile commands are always two words
+        match (find_second_space(cmd), find_separator(cmd)) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    } else {
+        find_separator(cmd)
+    };
+
+    match split {
+        Some(i) => {
+            let rest = cmd[i + 1..].trim();
+            (cmd[..i].trim(), Some(rest).filter(|r| !r.is_empty()))
+        }
+        None => (cmd, None),
+    }
+}
 
-                split = i;
+fn find_second_space(cmd: &str) -> Option<usize> {
+    let mut lookback = ' ';
+    let mut seen = false;
+
+    for (i, c) in cmd.char_indices() {
+        if c.is_whitespace() && !lookback.is_whitespace() {
+            if seen {
+                return Some(i);
             }
 
-            lookback = c;
+            seen = true;
         }
 
-        if found {
-            let (car, cdr) = cmd.split_at(split);
-            vec![car, cdr]
-        } else {
-            vec![cmd]
+        lookback = c;
+    }
+
+    None
+}
+
+// The first separator that isn't quoted, commented out, or part of an option
+// value. Commas are fine in option values, like "--tags 1,2", but semicolons
+// and newlines always end a command.
+fn find_separator(cmd: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut comment = false;
+    let mut word_start = None;
+    let mut after_option = false;
+
+    for (i, c) in cmd.char_indices() {
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+
+            continue;
+        }
+
+        if c == '\n' || (c == ';' && !comment) {
+            return Some(i);
+        }
+
+        if comment {
+            continue;
         }
-    } else {
-        cmd.splitn(2, ',').collect()
-    };
 
-    let car = parts[0];
+        if c.is_whitespace() {
+            if let Some(start) = word_start.take() {
+                let word = &cmd[start..i];
+                after_option = word.starts_with("--") && !FLAGS.contains(&word);
+            }
 
-    let cdr = match parts.get(1) {
-        Some(m) => {
-            let trimmed = m.trim();
+            continue;
+        }
 
-            if trimmed.is_empty() {
-                None
-            } else {
-                Some(trimmed)
+        if word_start.is_none() {
+            if c == '#' {
+                comment = true;
+                continue;
+            }
+
+            word_start = Some(i);
+
+            if c == '"' || c == '\'' {
+                quote = Some(c);
+                continue;
             }
         }
-        _ => None,
+
+        // a comma inside an option's value is part of it, but one at the end
+        // still ends the command
+        let value_goes_on = cmd[i + 1..].starts_with(|d: char| !d.is_whitespace());
+
+        if c == ',' && !(after_option && value_goes_on) {
+            return Some(i);
+        }
+    }
+
+    None
+}
+
+// "alias name = cmds", where the commands are quoted, or run to the end of the
+// line. Returns the name and commands, along with whatever comes after them.
+#[allow(clippy::type_complexity)]
+pub fn split_alias(cmd: &str) -> Option<(Result<(&str, &str), ParseError>, Option<&str>)> {
+    if !cmd.strip_prefix("alias")?.starts_with([' ', '\t']) {
+        return None;
+    }
+
+    let (line, next_line) = match cmd.find('\n') {
+        Some(i) => (&cmd[..i], Some(&cmd[i + 1..])),
+        None => (cmd, None),
     };
 
-    (car, cdr)
+    let next_line = next_line.filter(|r| !r.trim().is_empty());
+
+    let name_start = skip_blanks(line, "alias".len());
+    let name_end = line[name_start..]
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .map_or(line.len(), |i| name_start + i);
+
+    let name = &line[name_start..name_end];
+    let equals = skip_blanks(line, name_end);
+
+    if name.is_empty()
+        || name.starts_with('-')
+        || name.contains([',', ';', '#', '"', '\''])
+        || !line[equals..].starts_with('=')
+    {
+        let error = ParseError {
+            kind: ParseErrorKind::Usage("alias [name] = [commands]"),
+            position: name_start,
+        };
+
+        return Some((Err(error), next_line));
+    }
+
+    let body_start = skip_blanks(line, equals + 1);
+
+    match line[body_start..].chars().next() {
+        // quoted commands can go on for more than one line
+        Some(q) if q == '"' || q == '\'' => match cmd[body_start + 1..].find(q) {
+            Some(i) => {
+                let end = body_start + 1 + i;
+                let rest = cmd[end + 1..].trim_start();
+                let rest = rest.strip_prefix([',', ';']).unwrap_or(rest).trim_start();
+
+                Some((
+                    Ok((name, &cmd[body_start + 1..end])),
+                    Some(rest).filter(|r| !r.is_empty()),
+                ))
+            }
+            None => {
+                let error = ParseError {
+                    kind: ParseErrorKind::UnterminatedQuote,
+                    position: body_start,
+                };
+
+                Some((Err(error), None))
+            }
+        },
+        _ => Some((Ok((name, line[body_start..].trim_end())), next_line)),
+    }
+}
+
+fn skip_blanks(s: &str, from: usize) -> usize {
+    from + s[from..].len() - s[from..].trim_start_matches([' ', '\t']).len()
 }
 
-fn find_option<'a>(option: &'a str, cmd: &'a str) -> Option<&'a str> {
-    let parts: Vec<&str> = cmd.split(' ').collect();
+// A command that's nothing but a single word and options, which could be an
+// alias, split into the word and the options.
+pub fn split_alias_call(cmd: &str) -> Option<(&str, String)> {
+    let words = parse_statement(cmd).ok()?.words;
+
+    match words.as_slice() {
+        [word] if !word.quoted => Some((word.text, without_words(cmd, &words))),
+        _ => None,
+    }
+}
 
-    if let Some(i) = parts.iter().position(|s| *s == option) {
-        if let Some(next) = parts.get(i + 1) {
-            return Some(next);
+// Add options and flags to a command, leaving out any option it already has
+// its own of. The tag options count as one, since only one of them is used.
+pub fn with_options(cmd: &str, options: &str) -> String {
+    let (own, added) = match (parse_statement(cmd), parse_statement(options)) {
+        (Ok(own), Ok(added)) => (own, added),
+        _ => return format!("{} {}", cmd, options).trim().to_string(),
+    };
+
+    let tags = ["--tags", "--tagmask", "--tagset"];
+    let same = |a: &str, b: &str| a == b || (tags.contains(&a) && tags.contains(&b));
+
+    let mut parts = vec![cmd.trim().to_string()];
+
+    for (name, value) in added.options {
+        if !own.options.iter().any(|(n, _)| same(n, name)) {
+            parts.push(format!(
+                "{} {}",
+                name,
+                &options[value.position..value.end()]
+            ));
+        }
+    }
+
+    for flag in added.flags {
+        if !own.flags.contains(&flag) {
+            parts.push(flag.to_string());
         }
     }
 
-    Option::None
+    parts.join(" ")
 }
 
-fn remove_options<'a>(parts: &[&'a str]) -> Vec<&'a str> {
-    let mut ret: Vec<&str> = Vec::new();
-    let mut remove_next = false;
+// everything but the words, which leaves the options and flags
+fn without_words(cmd: &str, words: &[Token]) -> String {
+    let mut parts = Vec::new();
+    let mut start = 0;
 
-    for item in parts.iter() {
-        if item.starts_with("--") {
-            remove_next = true;
-        } else if remove_next {
-            remove_next = false;
-        } else {
-            ret.push(item);
-        }
+    for word in words {
+        parts.push(cmd[start..word.position].trim());
+        start = word.end();
     }
 
-    ret
+    parts.push(cmd[start..].trim());
+    parts.retain(|p| !p.is_empty());
+    parts.join(" ")
+}
+
+// nothing but whitespace and comments
+fn is_empty(cmd: &str) -> bool {
+    tokenize(cmd).is_ok_and(|tokens| tokens.is_empty())
+}
+
+pub fn has_flag(flag: &str, cmd: &str) -> bool {
+    parse_statement(cmd).is_ok_and(|s| s.flags.contains(&flag))
+}
+
+// the value of an option, if it's there, with f turning it into something
+// useful or failing
+fn parse_option<'a, T>(
+    option: &str,
+    cmd: &'a str,
+    f: impl Fn(&'a str) -> Option<T>,
+) -> Result<Option<T>, ParseError> {
+    let value = parse_statement(cmd)?
+        .options
+        .into_iter()
+        .find(|(name, _)| *name == option)
+        .map(|(_, value)| value);
+
+    match value {
+        Some(value) => match f(value.text) {
+            Some(v) => Ok(Some(v)),
+            None => Err(ParseError {
+                kind: ParseErrorKind::InvalidValue {
+                    option: option.to_string(),
+                    value: value.text.to_string(),
+                    suggestion: None,
+                },
+                position: value.position,
+            }),
+        },
+        None => Ok(None),
+    }
 }
 
 #[cfg(test)]
@@ -255,19 +900,238 @@ mod tests {
 
         assert_eq!(car, "-some-command 47");
         assert_eq!(cdr, None);
+
+        let (car, cdr) = split_commands("--tags 1,3 flip, --output DP-1 pad,monocle");
+
+        assert_eq!(car, "--tags 1,3 flip");
+        assert_eq!(cdr, Some("--output DP-1 pad,monocle"));
+
+        let (car, cdr) = split_commands(cdr.unwrap());
+
+        assert_eq!(car, "--output DP-1 pad");
+        assert_eq!(cdr, Some("monocle"));
+
+        // options can come last
+        let (car, cdr) = split_commands("flip --tags 1, pad on");
+
+        assert_eq!(car, "flip --tags 1");
+        assert_eq!(cdr, Some("pad on"));
+
+        let (car, cdr) = split_commands("flip --tags 1,2,");
+
+        assert_eq!(car, "flip --tags 1,2");
+        assert_eq!(cdr, None);
+    }
+
+    #[test]
+    fn it_splits_on_any_separator() {
+        let (car, cdr) = split_commands("flip; pad\nmonocle");
+
+        assert_eq!(car, "flip");
+        assert_eq!(cdr, Some("pad\nmonocle"));
+
+        let (car, cdr) = split_commands(cdr.unwrap());
+
+        assert_eq!(car, "pad");
+        assert_eq!(cdr, Some("monocle"));
+
+        let (car, cdr) = split_commands("preset-save \"a, b\"; flip");
+
+        assert_eq!(car, "preset-save \"a, b\"");
+        assert_eq!(cdr, Some("flip"));
+
+        let (car, cdr) = split_commands("flip # then, pad\nmonocle");
+
+        assert_eq!(car, "flip # then, pad");
+        assert_eq!(cdr, Some("monocle"));
+    }
+
+    #[test]
+    fn it_skips_empty_commands() {
+        let (car, cdr) = split_commands("# first a comment\n\nflip,, # and another");
+
+        assert_eq!(car, "flip");
+        assert_eq!(cdr, None);
+
+        let (car, cdr) = split_commands("flip ;; pad");
+
+        assert_eq!(car, "flip");
+        assert_eq!(cdr, Some("; pad"));
+        assert_eq!(split_commands(cdr.unwrap()), ("pad", None));
+    }
+
+    #[test]
+    fn it_parses_cycles() {
+        assert_eq!(
+            Some(Ok(Cycle {
+                setting: "main-ratio",
+                values: vec!["50", "60", "70"],
+                options: "--tags 1 --ephemeral".to_string()
+            })),
+            parse_cycle("--tags 1 cycle main-ratio 50 60 70 --ephemeral")
+        );
+
+        assert_eq!(
+            Some(Ok(Cycle {
+                setting: "preset-load",
+                values: vec!["'two up'", "default"],
+                options: String::new()
+            })),
+            parse_cycle("toggle preset-load 'two up' default")
+        );
+
+        assert_eq!(
+            Some(Err(ParseError {
+                kind: ParseErrorKind::Usage("toggle [setting] [a] [b]"),
+                position: 0
+            })),
+            parse_cycle("toggle view-padding 0 6 12")
+        );
+
+        assert!(parse_cycle("cycle main-ratio").unwrap().is_err());
+        assert_eq!(None, parse_cycle("view-padding 4"));
+    }
+
+    #[test]
+    fn it_splits_aliases() {
+        let (definition, rest) =
+            split_alias("alias focus = monocle on, view-padding 0\nflip").unwrap();
+
+        assert_eq!(Ok(("focus", "monocle on, view-padding 0")), definition);
+        assert_eq!(Some("flip"), rest);
+
+        let (definition, rest) =
+            split_alias("alias  focus='monocle on, pad on'; flip, pad").unwrap();
+
+        assert_eq!(Ok(("focus", "monocle on, pad on")), definition);
+        assert_eq!(Some("flip, pad"), rest);
+
+        let (definition, rest) = split_alias("alias focus monocle on\nflip").unwrap();
+
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::Usage("alias [name] = [commands]"),
+                position: 6
+            }),
+            definition
+        );
+        assert_eq!(Some("flip"), rest);
+
+        assert_eq!(None, split_alias("aliases = 4"));
+        assert_eq!(None, split_alias("flip, alias a = pad"));
+    }
+
+    #[test]
+    fn it_splits_alias_calls() {
+        assert_eq!(
+            Some(("focus", "--tags 1 --output DP-1".to_string())),
+            split_alias_call("--tags 1 focus --output DP-1")
+        );
+
+        assert_eq!(Some(("focus", String::new())), split_alias_call("focus"));
+        assert_eq!(None, split_alias_call("view-padding 4"));
+    }
+
+    #[test]
+    fn it_adds_options() {
+        assert_eq!(
+            "pad on --tags 1 --output 'DP 1' --cascade",
+            with_options("pad on", "--tags 1 --output 'DP 1' --cascade")
+        );
+
+        // a command's own options win
+        assert_eq!(
+            "--tagset 3 flip --force --output DP-1",
+            with_options("--tagset 3 flip --force", "--tags 1 --output DP-1 --force")
+        );
+
+        assert_eq!("flip", with_options("flip", ""));
+    }
+
+    #[test]
+    fn it_tokenizes() {
+        let tokens = tokenize("  main-ratio\t 60  # a comment").unwrap();
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text).collect();
+
+        assert_eq!(vec!["main-ratio", "60"], texts);
+        assert_eq!(14, tokens[1].position);
+
+        let tokens = tokenize("preset-save 'my preset'").unwrap();
+
+        assert_eq!(
+            Token {
+                text: "my preset",
+                position: 12,
+                quoted: true
+            },
+            tokens[1]
+        );
     }
 
     #[test]
     fn it_parses_invalid_commands() {
         assert_eq!(
-            Command::Invalid,
+            Err(ParseError {
+                kind: ParseErrorKind::TooManyArguments,
+                position: 23
+            }),
             parse_command("free-ice-cream for you and me")
         );
+
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::UnterminatedQuote,
+                position: 12
+            }),
+            parse_command("preset-save \"oops")
+        );
+
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::MissingValue("--tags".to_string()),
+                position: 5
+            }),
+            parse_command("flip --tags")
+        );
+
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::MissingValue("--tags".to_string()),
+                position: 0
+            }),
+            parse_command("--tags --output DP-1 flip")
+        );
+
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::UnknownOption("--tgas".to_string()),
+                position: 0
+            }),
+            parse_command("--tgas 1 flip")
+        );
+
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::InvalidNumber("+lots".to_string()),
+                position: 9
+            }),
+            parse_command("diminish +lots")
+        );
+
+        assert_eq!(
+            Err(ParseError {
+                kind: ParseErrorKind::MissingCommand,
+                position: 8
+            }),
+            parse_command("--tags 1")
+        );
+
+        assert!(parse_tags("--tags flip", &HashMap::new()).is_err());
     }
 
     #[test]
     fn it_parses_single_commands() {
-        match parse_command("flip") {
+        match parse_command("flip").unwrap() {
             Command::Single(v) => assert_eq!("flip", v),
             _ => panic!("parser fail"),
         };
@@ -275,38 +1139,235 @@ mod tests {
 
     #[test]
     fn it_ignores_options() {
-        match parse_command("--output HD1 flip --tags 1") {
+        match parse_command("--output HD1 flip --tags 1").unwrap() {
             Command::Single(v) => assert_eq!("flip", v),
             _ => panic!("parser fail"),
         };
     }
 
+    #[test]
+    fn it_ignores_flags() {
+        match parse_command("--cascade flip --tags 1").unwrap() {
+            Command::Single(v) => assert_eq!("flip", v),
+            _ => panic!("parser fail"),
+        };
+
+        assert!(has_flag("--cascade", "view-padding 4 --cascade"));
+        assert!(!has_flag("--cascade", "view-padding 4"));
+
+        let (car, cdr) = split_commands("--cascade flip, pad");
+
+        assert_eq!(car, "--cascade flip");
+        assert_eq!(cdr, Some("pad"));
+    }
+
     #[test]
     fn it_parses_options() {
-        match parse_output("--output HD1 flip") {
+        match parse_output("--output HD1 flip").unwrap() {
             Some(o) => assert_eq!(AllOrOne::One("HD1"), o),
             _ => panic!("parser fail"),
         }
 
-        match parse_output("--output all flip") {
+        match parse_output("--output all flip").unwrap() {
             Some(o) => assert_eq!(AllOrOne::All, o),
             _ => panic!("parser fail"),
         }
 
-        match parse_tags("flip --tags all") {
+        match parse_tags("flip --tags all", &HashMap::new()).unwrap() {
             Some(t) => assert_eq!(AllOrOne::All, t),
             _ => panic!("parser fail"),
         }
 
-        match parse_tags("flip --tags 32") {
-            Some(t) => assert_eq!(AllOrOne::One(32), t),
+        match parse_tags("flip --tagmask 32", &HashMap::new()).unwrap() {
+            Some(t) => assert_eq!(AllOrOne::One(vec![32]), t),
             _ => panic!("parser fail"),
         }
+
+        assert_eq!(
+            Some(3),
+            parse_tagset("--tagset 1,2 flip", &HashMap::new()).unwrap()
+        );
+        assert_eq!(Some(-2), parse_priority("--priority -2 flip").unwrap());
+        assert_eq!(
+            Some(Scope::Output),
+            parse_scope("--scope current-output flip").unwrap()
+        );
+        assert_eq!(
+            Some(Scope::TagOutput),
+            parse_scope("--scope tag+output flip").unwrap()
+        );
+        assert!(parse_scope("--scope nowhere flip").is_err());
+        assert_eq!(
+            None,
+            parse_tagset("--tags 3 flip", &HashMap::new()).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_parses_tag_lists() {
+        let names = HashMap::new();
+
+        assert_eq!(
+            Some(AllOrOne::One(vec![64])),
+            parse_tags("--tags 7 flip", &names).unwrap()
+        );
+
+        assert_eq!(
+            Some(AllOrOne::One(vec![1, 4, 16])),
+            parse_tags("--tags 1,3,5 flip", &names).unwrap()
+        );
+
+        assert_eq!(
+            Some(AllOrOne::One(vec![1, 2, 4, 8, 64])),
+            parse_tags("--tags 1-4,7,2 flip", &names).unwrap()
+        );
+
+        assert_eq!(
+            Some(AllOrOne::One(vec![2, 8])),
+            parse_tags("--tagmask 10 flip", &names).unwrap()
+        );
+
+        assert!(parse_tags("--tags 0 flip", &names).is_err());
+        assert!(parse_tags("--tags 33 flip", &names).is_err());
+        assert!(parse_tags("--tags 4-2 flip", &names).is_err());
+        assert!(parse_tags("--tags one flip", &names).is_err());
+    }
+
+    #[test]
+    fn it_parses_tag_names() {
+        let names = HashMap::from([("web".to_string(), 1), ("scratch".to_string(), 7)]);
+
+        assert_eq!(
+            Some(AllOrOne::One(vec![1, 64])),
+            parse_tags("--tags web,scratch flip", &names).unwrap()
+        );
+
+        assert_eq!(
+            Some(AllOrOne::One(vec![1, 2, 4])),
+            parse_tags("--tags web-3 flip", &names).unwrap()
+        );
+
+        assert_eq!(
+            Some(65),
+            parse_tagset("--tagset web,7 flip", &names).unwrap()
+        );
+        assert!(parse_tags("--tags code flip", &names).is_err());
+
+        let err = parse_tags("--tags 2,wb flip", &names).unwrap_err();
+
+        assert_eq!(
+            ParseErrorKind::InvalidValue {
+                option: "--tags".to_string(),
+                value: "2,wb".to_string(),
+                suggestion: Some("web".to_string())
+            },
+            err.kind
+        );
+
+        assert_eq!(
+            "invalid value 2,wb for --tags at column 8, did you mean web?",
+            err.to_string()
+        );
+
+        let err = parse_scope("--scope globl flip").unwrap_err();
+        assert_eq!(
+            "invalid value globl for --scope at column 9, did you mean global?",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn it_parses_keyed_commands() {
+        assert_eq!(
+            Command::Keyed {
+                namespace: "tag-name",
+                key: "7",
+                value: "scratch"
+            },
+            parse_command("tag-name 7 scratch").unwrap()
+        );
+    }
+
+    #[test]
+    fn it_parses_quoted_values() {
+        assert_eq!(
+            Command::Textual {
+                namespace: "preset-save",
+                value: "my preset"
+            },
+            parse_command("preset-save \"my preset\"").unwrap()
+        );
+
+        assert_eq!(
+            Command::Textual {
+                namespace: "preset-save",
+                value: "7"
+            },
+            parse_command("preset-save '7'").unwrap()
+        );
+
+        assert_eq!(
+            Some(AllOrOne::One("DP 1")),
+            parse_output("--output 'DP 1' flip").unwrap()
+        );
+    }
+
+    #[test]
+    fn it_parses_explicit_sets() {
+        let set = |value| Command::Numeric {
+            namespace: "diminish",
+            operation: Operation::Set,
+            value,
+        };
+
+        assert_eq!(set(-50), parse_command("diminish =-50").unwrap());
+        assert_eq!(set(50), parse_command("diminish =50").unwrap());
+        assert_eq!(set(-50), parse_command("diminish = -50").unwrap());
+        assert_eq!(set(-5), parse_command("diminish =-0.05").unwrap());
+        assert!(parse_command("diminish +-50").is_err());
+        assert!(parse_command("diminish = left").is_err());
+    }
+
+    #[test]
+    fn it_parses_rules() {
+        let (condition, cmd) = parse_rule("--tags 1 when views > 4 main-count 2").unwrap();
+
+        assert_eq!(
+            Condition {
+                subject: Subject::Views,
+                comparison: Comparison::Greater,
+                value: Operand::Value(4)
+            },
+            condition
+        );
+
+        assert_eq!("main-count 2", cmd);
+
+        let (condition, cmd) = parse_rule("when height > width main-location top").unwrap();
+
+        assert_eq!(
+            Condition {
+                subject: Subject::Height,
+                comparison: Comparison::Greater,
+                value: Operand::Subject(Subject::Width)
+            },
+            condition
+        );
+
+        assert_eq!("main-location top", cmd);
+
+        let (_, cmd) = parse_rule("when  views  > 1  preset-load  'two up'  # busy").unwrap();
+
+        assert_eq!("preset-load  'two up'", cmd);
+
+        assert_eq!(None, parse_rule("when views >> 4 main-count 2"));
+        assert_eq!(None, parse_rule("when windows > 4 main-count 2"));
+        assert_eq!(None, parse_rule("when views > 4"));
     }
 
     #[test]
     fn it_parses_numeric_commands() {
-        match parse_command("outer-padding +3") {
+        match parse_command("outer-padding +3").unwrap() {
             Command::Numeric {
                 namespace: ns,
                 operation: op,
@@ -319,7 +1380,7 @@ mod tests {
             _ => panic!("parser fail"),
         };
 
-        match parse_command("inner-padding -3") {
+        match parse_command("inner-padding -3").unwrap() {
             Command::Numeric {
                 namespace: ns,
                 operation: op,
@@ -332,7 +1393,7 @@ mod tests {
             _ => panic!("parser fail"),
         };
 
-        match parse_command("main-ratio 75") {
+        match parse_command("main-ratio    75").unwrap() {
             Command::Numeric {
                 namespace: ns,
                 operation: op,
@@ -345,7 +1406,7 @@ mod tests {
             _ => panic!("parser fail"),
         };
 
-        match parse_command("main-ratio 0.75") {
+        match parse_command("main-ratio 0.75").unwrap() {
             Command::Numeric {
                 namespace: ns,
                 operation: op,
@@ -361,7 +1422,7 @@ mod tests {
 
     #[test]
     fn it_parses_textual_commands() {
-        match parse_command("main-location left") {
+        match parse_command("main-location left").unwrap() {
             Command::Textual {
                 namespace: ns,
                 value: v,
@@ -375,7 +1436,7 @@ mod tests {
 
     #[test]
     fn it_parses_rivertile_commands() {
-        match parse_command("-main-location right") {
+        match parse_command("-main-location right").unwrap() {
             Command::Textual {
                 namespace: ns,
                 value: v,
@@ -386,7 +1447,7 @@ mod tests {
             _ => panic!("parser fail"),
         }
 
-        match parse_command("-main-ratio 0.6") {
+        match parse_command("-main-ratio 0.6").unwrap() {
             Command::Numeric {
                 namespace: ns,
                 operation: op,
@@ -0,0 +1,133 @@
+use crate::tile::Params;
+
+// What a condition looks at.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Subject {
+    Views,
+    Width,
+    Height,
+}
+
+impl Subject {
+    fn get(&self, params: &Params) -> u32 {
+        match self {
+            Subject::Views => params.view_count,
+            Subject::Width => params.usable_width,
+            Subject::Height => params.usable_height,
+        }
+    }
+}
+
+// What a subject is compared to.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Operand {
+    Value(u32),
+    Subject(Subject),
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+// Something like "views > 4" or "height > width", which decides whether a rule
+// applies to a layout.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Condition {
+    pub subject: Subject,
+    pub comparison: Comparison,
+    pub value: Operand,
+}
+
+impl Condition {
+    pub fn matches(&self, params: &Params) -> bool {
+        let actual = self.subject.get(params);
+
+        let value = match self.value {
+            Operand::Value(v) => v,
+            Operand::Subject(s) => s.get(params),
+        };
+
+        match self.comparison {
+            Comparison::Less => actual < value,
+            Comparison::LessOrEqual => actual <= value,
+            Comparison::Equal => actual == value,
+            Comparison::NotEqual => actual != value,
+            Comparison::GreaterOrEqual => actual >= value,
+            Comparison::Greater => actual > value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_matches_view_counts() {
+        let params = Params {
+            view_count: 4,
+            usable_width: 1920,
+            usable_height: 1080,
+        };
+
+        let condition = |comparison, value| Condition {
+            subject: Subject::Views,
+            comparison,
+            value: Operand::Value(value),
+        };
+
+        assert!(condition(Comparison::Greater, 3).matches(&params));
+        assert!(!condition(Comparison::Greater, 4).matches(&params));
+        assert!(condition(Comparison::GreaterOrEqual, 4).matches(&params));
+        assert!(condition(Comparison::Equal, 4).matches(&params));
+        assert!(!condition(Comparison::NotEqual, 4).matches(&params));
+        assert!(condition(Comparison::LessOrEqual, 4).matches(&params));
+        assert!(!condition(Comparison::Less, 4).matches(&params));
+    }
+
+    #[test]
+    fn it_matches_dimensions() {
+        let params = Params {
+            view_count: 4,
+            usable_width: 1920,
+            usable_height: 1080,
+        };
+
+        let condition = |subject, comparison, value| Condition {
+            subject,
+            comparison,
+            value,
+        };
+
+        assert!(condition(
+            Subject::Width,
+            Comparison::GreaterOrEqual,
+            Operand::Value(1920)
+        )
+        .matches(&params));
+
+        assert!(
+            !condition(Subject::Height, Comparison::Greater, Operand::Value(1080)).matches(&params)
+        );
+
+        assert!(condition(
+            Subject::Width,
+            Comparison::Greater,
+            Operand::Subject(Subject::Height)
+        )
+        .matches(&params));
+
+        assert!(!condition(
+            Subject::Height,
+            Comparison::Greater,
+            Operand::Subject(Subject::Width)
+        )
+        .matches(&params));
+    }
+}
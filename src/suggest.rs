@@ -0,0 +1,61 @@
+// The candidate closest to a word, as long as it's close enough to be a
+// likely typo. Ties go to whichever comes first alphabetically.
+pub fn closest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (word.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .map(|c| (distance(word, c), c))
+        .filter(|(d, c)| *d <= limit && *c != word)
+        .min()
+        .map(|(_, c)| c)
+}
+
+// how many characters have to be added, removed or changed to turn one word
+// into the other
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+
+            current.push(
+                (previous[j] + cost)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_measures_distance() {
+        assert_eq!(0, distance("flip", "flip"));
+        assert_eq!(1, distance("lef", "left"));
+        assert_eq!(1, distance("view-paddding", "view-padding"));
+        assert_eq!(2, distance("mian", "main"));
+        assert_eq!(4, distance("", "flip"));
+    }
+
+    #[test]
+    fn it_finds_the_closest() {
+        let values = ["left", "top", "right", "bottom"];
+
+        assert_eq!(Some("left"), closest("lef", values));
+        assert_eq!(Some("right"), closest("rght", values));
+        assert_eq!(None, closest("center", values));
+        assert_eq!(None, closest("left", values));
+    }
+}